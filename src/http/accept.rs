@@ -0,0 +1,229 @@
+use std::{cmp::Reverse, fmt, str::FromStr};
+
+use super::{
+    error::HttpParseError,
+    headers::{header_params, split_list},
+};
+
+/// One element of an `Accept`-style list, e.g. `text/html;level=1;q=0.5`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QualityItem {
+    /// Media range, coding or language range, lower-cased.
+    pub value: String,
+    /// Parameters other than `q`, such as `level=1`.
+    pub params: Vec<(String, String)>,
+    /// Weight in thousandths, 0 meaning "not acceptable".
+    pub quality: u16,
+}
+
+/// A weighted list from `Accept`, `Accept-Encoding`, `Accept-Charset` or
+/// `Accept-Language`. Elements with a malformed q-value are ignored.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct QualityList {
+    items: Vec<QualityItem>,
+}
+
+impl QualityList {
+    pub fn new() -> Self {
+        QualityList { items: Vec::new() }
+    }
+
+    /// Merges the elements of another header of the same name.
+    pub fn extend(&mut self, other: QualityList) {
+        self.items.extend(other.items);
+    }
+
+    /// Elements in the order they were sent.
+    pub fn items(&self) -> &[QualityItem] {
+        &self.items
+    }
+
+    /// Elements from most to least preferred, keeping the sent order among
+    /// equal weights.
+    pub fn by_preference(&self) -> Vec<&QualityItem> {
+        let mut items: Vec<&QualityItem> = self.items.iter().collect();
+        items.sort_by_key(|item| Reverse(item.quality));
+        items
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Weight of `candidate` given by the most specific matching element,
+    /// or `None` if no element matches. `text/*` and `*/*` match media
+    /// types, `*` anything, and `en` matches `en-US`.
+    pub fn quality(&self, candidate: &str) -> Option<u16> {
+        self.items
+            .iter()
+            .filter_map(|item| specificity(&item.value, candidate).map(|s| (s, item.quality)))
+            .max_by_key(|(s, _)| *s)
+            .map(|(_, quality)| quality)
+    }
+
+    /// The acceptable candidate with the highest weight. Ties go to the
+    /// earlier candidate, so callers list them in their own preference.
+    pub fn negotiate<'a>(&self, candidates: &[&'a str]) -> Option<&'a str> {
+        let mut best: Option<(&'a str, u16)> = None;
+
+        for candidate in candidates {
+            let quality = self.quality(candidate).unwrap_or(0);
+            if quality > 0 && best.is_none_or(|(_, q)| quality > q) {
+                best = Some((candidate, quality));
+            }
+        }
+
+        best.map(|(candidate, _)| candidate)
+    }
+}
+
+/// How closely `range` matches `candidate`, higher being more specific.
+fn specificity(range: &str, candidate: &str) -> Option<u8> {
+    let candidate = candidate.to_ascii_lowercase();
+
+    if range == candidate {
+        return Some(3);
+    }
+    if let Some(prefix) = range.strip_suffix("/*")
+        && prefix != "*"
+        && candidate.split_once('/').is_some_and(|(t, _)| t == prefix)
+    {
+        return Some(2);
+    }
+    if candidate
+        .strip_prefix(range)
+        .is_some_and(|rest| rest.starts_with('-'))
+    {
+        return Some(2);
+    }
+    if range == "*" || range == "*/*" {
+        return Some(1);
+    }
+
+    None
+}
+
+/// Parses a qvalue, `0` to `1` with up to three decimals, into thousandths.
+fn parse_quality(s: &str) -> Option<u16> {
+    let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+    if !matches!(int, "0" | "1") || frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let frac: u16 = format!("{:0<3}", frac).parse().ok()?;
+    let quality = int.parse::<u16>().ok()? * 1000 + frac;
+
+    (quality <= 1000).then_some(quality)
+}
+
+impl FromStr for QualityList {
+    type Err = HttpParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut items = Vec::new();
+
+        for element in split_list(s) {
+            let value = element.split(';').next().unwrap_or("").trim();
+            if value.is_empty() {
+                return Err(HttpParseError::BadHeader);
+            }
+
+            let mut quality = Some(1000);
+            let mut params = Vec::new();
+            for (name, param) in header_params(element) {
+                if name == "q" {
+                    quality = parse_quality(&param);
+                } else {
+                    params.push((name, param));
+                }
+            }
+
+            if let Some(quality) = quality {
+                items.push(QualityItem {
+                    value: value.to_ascii_lowercase(),
+                    params,
+                    quality,
+                });
+            }
+        }
+
+        Ok(QualityList { items })
+    }
+}
+
+impl fmt::Display for QualityList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, item) in self.items.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", item.value)?;
+            for (name, value) in &item.params {
+                write!(f, ";{}={}", name, value)?;
+            }
+            if item.quality < 1000 {
+                let q = format!("{}.{:03}", item.quality / 1000, item.quality % 1000);
+                write!(f, ";q={}", q.trim_end_matches('0').trim_end_matches('.'))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_weights_and_params() {
+        let accept = "text/html;level=1, text/*;q=0.3, */*;q=0.05, image/png;q=2"
+            .parse::<QualityList>()
+            .unwrap();
+
+        let items = accept.items();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].value, "text/html");
+        assert_eq!(
+            items[0].params,
+            vec![("level".to_string(), "1".to_string())]
+        );
+        assert_eq!(items[1].quality, 300);
+        assert_eq!(items[2].quality, 50);
+        assert_eq!(
+            accept.to_string(),
+            "text/html;level=1, text/*;q=0.3, */*;q=0.05"
+        );
+    }
+
+    #[test]
+    fn picks_most_specific_match() {
+        let accept = "text/*;q=0.3, text/html, */*;q=0.1"
+            .parse::<QualityList>()
+            .unwrap();
+
+        assert_eq!(accept.quality("text/html"), Some(1000));
+        assert_eq!(accept.quality("text/plain"), Some(300));
+        assert_eq!(accept.quality("image/png"), Some(100));
+
+        let languages = "de-CH, en;q=0.8".parse::<QualityList>().unwrap();
+        assert_eq!(languages.quality("en-US"), Some(800));
+        assert_eq!(languages.quality("fr"), None);
+    }
+
+    #[test]
+    fn negotiates_best_candidate() {
+        let encodings = "gzip;q=0.8, br, identity;q=0"
+            .parse::<QualityList>()
+            .unwrap();
+
+        assert_eq!(encodings.negotiate(&["gzip", "br"]), Some("br"));
+        assert_eq!(encodings.negotiate(&["deflate", "gzip"]), Some("gzip"));
+        assert_eq!(encodings.negotiate(&["identity"]), None);
+        assert_eq!(
+            encodings.by_preference()[0].value,
+            "br",
+            "highest weight first"
+        );
+    }
+}
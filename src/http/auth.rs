@@ -0,0 +1,186 @@
+use std::{fmt, str::FromStr};
+
+use super::{error::HttpParseError, headers::is_token};
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Credentials from an `Authorization` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Authorization {
+    /// RFC 7617, with the user-id and password already decoded.
+    Basic { user: String, password: String },
+    /// RFC 6750 bearer token.
+    Bearer(String),
+    /// Any other scheme, with its credentials left as sent.
+    Other { scheme: String, credentials: String },
+}
+
+impl Authorization {
+    pub fn scheme(&self) -> &str {
+        match self {
+            Authorization::Basic { .. } => "Basic",
+            Authorization::Bearer(_) => "Bearer",
+            Authorization::Other { scheme, .. } => scheme,
+        }
+    }
+}
+
+impl FromStr for Authorization {
+    type Err = HttpParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, credentials) = s.trim().split_once(' ').unwrap_or((s.trim(), ""));
+        let credentials = credentials.trim();
+        if !is_token(scheme) {
+            return Err(HttpParseError::BadHeader);
+        }
+
+        if scheme.eq_ignore_ascii_case("basic") {
+            let decoded = base64_decode(credentials).ok_or(HttpParseError::BadHeader)?;
+            let decoded = String::from_utf8(decoded).map_err(|_| HttpParseError::BadHeader)?;
+            let (user, password) = decoded.split_once(':').ok_or(HttpParseError::BadHeader)?;
+
+            return Ok(Authorization::Basic {
+                user: user.to_string(),
+                password: password.to_string(),
+            });
+        }
+
+        if scheme.eq_ignore_ascii_case("bearer") {
+            if credentials.is_empty() || credentials.contains(' ') {
+                return Err(HttpParseError::BadHeader);
+            }
+            return Ok(Authorization::Bearer(credentials.to_string()));
+        }
+
+        Ok(Authorization::Other {
+            scheme: scheme.to_string(),
+            credentials: credentials.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Authorization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Authorization::Basic { user, password } => {
+                let credentials = format!("{}:{}", user, password);
+                write!(f, "Basic {}", base64_encode(credentials.as_bytes()))
+            }
+            Authorization::Bearer(token) => write!(f, "Bearer {}", token),
+            Authorization::Other {
+                scheme,
+                credentials,
+            } if credentials.is_empty() => write!(f, "{}", scheme),
+            Authorization::Other {
+                scheme,
+                credentials,
+            } => write!(f, "{} {}", scheme, credentials),
+        }
+    }
+}
+
+/// Standard padded base64.
+fn base64_encode(input: &[u8]) -> String {
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+
+    for chunk in input.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+/// Standard base64, padding required. `None` on any invalid input.
+fn base64_decode(input: &str) -> Option<Vec<u8>> {
+    let input = input.as_bytes();
+    if !input.len().is_multiple_of(4) {
+        return None;
+    }
+
+    let mut out = Vec::with_capacity(input.len() / 4 * 3);
+    for (index, chunk) in input.chunks(4).enumerate() {
+        let last = index == input.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|b| **b == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+
+        let mut n = 0u32;
+        for (i, b) in chunk[..4 - padding].iter().enumerate() {
+            let value = BASE64.iter().position(|c| c == b)? as u32;
+            n |= value << (18 - 6 * i);
+        }
+
+        out.extend_from_slice(&n.to_be_bytes()[1..4 - padding]);
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_schemes() {
+        assert_eq!(
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+                .parse::<Authorization>()
+                .unwrap(),
+            Authorization::Basic {
+                user: "Aladdin".to_string(),
+                password: "open sesame".to_string(),
+            }
+        );
+        assert_eq!(
+            "bearer mF_9.B5f-4.1JqM".parse::<Authorization>().unwrap(),
+            Authorization::Bearer("mF_9.B5f-4.1JqM".to_string())
+        );
+        assert_eq!(
+            "Digest username=\"x\"".parse::<Authorization>().unwrap(),
+            Authorization::Other {
+                scheme: "Digest".to_string(),
+                credentials: "username=\"x\"".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_credentials() {
+        for s in [
+            "Basic !!!",
+            "Basic QWxhZGRpbg==",
+            "Basic QQ=",
+            "Bearer",
+            "Bearer a b",
+            "B@d x",
+        ] {
+            assert!(s.parse::<Authorization>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn formats_credentials() {
+        let auth = Authorization::Basic {
+            user: "user".to_string(),
+            password: "pa:ss".to_string(),
+        };
+
+        assert_eq!(auth.to_string(), "Basic dXNlcjpwYTpzcw==");
+        assert_eq!(auth.to_string().parse::<Authorization>().unwrap(), auth);
+        assert_eq!(base64_encode(b"ab"), "YWI=");
+        assert_eq!(base64_decode("YWI="), Some(b"ab".to_vec()));
+    }
+}
@@ -0,0 +1,643 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, ready},
+    time::Duration,
+};
+
+use bytes::{Bytes, BytesMut};
+use futures_util::{Stream, stream};
+use tokio::{
+    io::{self, AsyncBufRead, AsyncRead, AsyncReadExt, ReadBuf},
+    sync::{mpsc, oneshot},
+    time::{Sleep, sleep},
+};
+
+use super::{chunked::ChunkedDecoder, error::HttpParseError, headers::HttpHeaders, limits::Limits};
+
+/// Largest piece handed out by the `RequestBody` stream.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Longest a streaming body may wait for more data, the same deadline the
+/// request head gets.
+pub const BODY_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Connection reader lent to a request body while its handler runs.
+pub type BoxedReader = Box<dyn AsyncBufRead + Send + Unpin>;
+
+/// How the end of a request body is found, as announced by its headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyFraming {
+    Empty,
+    Length(usize),
+    Chunked,
+}
+
+enum BodyKind {
+    Empty,
+    Buffered(Bytes),
+    Length(usize),
+    Chunked(ChunkedDecoder),
+}
+
+/// Request body that is read from the connection on demand. Handlers can
+/// consume it as an `AsyncRead`, as a `Stream` of pieces (also through
+/// `chunk`), or all at once with `collect`.
+pub struct RequestBody {
+    reader: Option<BoxedReader>,
+    kind: BodyKind,
+    continue_tx: Option<oneshot::Sender<()>>,
+    /// Read buffer behind the stream. Pieces are split off it, so its
+    /// allocation is reused once the handler drops them.
+    buf: BytesMut,
+    /// Running while a read waits on the connection, reset by every read
+    /// that completes.
+    idle: Option<Pin<Box<Sleep>>>,
+}
+
+impl RequestBody {
+    pub fn empty() -> Self {
+        RequestBody {
+            reader: None,
+            kind: BodyKind::Empty,
+            continue_tx: None,
+            buf: BytesMut::new(),
+            idle: None,
+        }
+    }
+
+    pub fn streaming(reader: BoxedReader, framing: BodyFraming, limits: &Limits) -> Self {
+        let kind = match framing {
+            BodyFraming::Empty => BodyKind::Empty,
+            BodyFraming::Length(len) => BodyKind::Length(len),
+            BodyFraming::Chunked => BodyKind::Chunked(ChunkedDecoder::new(limits)),
+        };
+
+        RequestBody {
+            reader: Some(reader),
+            kind,
+            continue_tx: None,
+            buf: BytesMut::new(),
+            idle: None,
+        }
+    }
+
+    /// Holds back the body until it is first read. The returned receiver fires
+    /// at that point, telling the connection to send `100 Continue`.
+    pub fn expect_continue(&mut self) -> oneshot::Receiver<()> {
+        let (tx, rx) = oneshot::channel();
+        self.continue_tx = Some(tx);
+        rx
+    }
+
+    /// True while the client is still waiting for `100 Continue`, i.e. the
+    /// body has not been sent and must not be drained.
+    pub fn is_awaiting_continue(&self) -> bool {
+        self.continue_tx.is_some()
+    }
+
+    /// Trailer fields of a chunked body, available once it has been read to
+    /// the end.
+    pub fn trailers(&self) -> Option<&HttpHeaders> {
+        match &self.kind {
+            BodyKind::Chunked(decoder) if decoder.is_done() => Some(decoder.trailers()),
+            _ => None,
+        }
+    }
+
+    /// Next piece of the body, or `None` once it has been fully read.
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, HttpParseError> {
+        // Imported locally, as `StreamExt::collect` would shadow our own
+        // `collect` in every module that globs this one.
+        use futures_util::StreamExt;
+
+        self.next().await.transpose()
+    }
+
+    /// Reads the rest of the body into memory. Meant for small bodies, whose
+    /// size is bounded by the route's `Limits`.
+    pub async fn collect(&mut self) -> Result<Vec<u8>, HttpParseError> {
+        let mut body = Vec::new();
+        self.read_to_end(&mut body).await?;
+
+        Ok(body)
+    }
+
+    /// Discards whatever the handler left unread and hands the connection
+    /// reader back, so the next request on a keep-alive connection starts at
+    /// the right byte.
+    pub async fn finish(mut self) -> Result<Option<BoxedReader>, HttpParseError> {
+        io::copy(&mut self, &mut io::sink()).await?;
+
+        Ok(self.reader.take())
+    }
+}
+
+impl Default for RequestBody {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl From<Vec<u8>> for RequestBody {
+    fn from(body: Vec<u8>) -> Self {
+        Bytes::from(body).into()
+    }
+}
+
+impl From<Bytes> for RequestBody {
+    fn from(body: Bytes) -> Self {
+        RequestBody {
+            reader: None,
+            kind: BodyKind::Buffered(body),
+            continue_tx: None,
+            buf: BytesMut::new(),
+            idle: None,
+        }
+    }
+}
+
+impl AsyncRead for RequestBody {
+    /// Fails with `TimedOut` once the connection has gone `BODY_READ_TIMEOUT`
+    /// without delivering any of the body, so a client that announces a body
+    /// and then goes quiet cannot hold the connection forever.
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if let Some(tx) = this.continue_tx.take() {
+            let _ = tx.send(());
+        }
+
+        let Poll::Ready(result) = this.poll_read_body(cx, buf) else {
+            let idle = this
+                .idle
+                .get_or_insert_with(|| Box::pin(sleep(BODY_READ_TIMEOUT)));
+            ready!(idle.as_mut().poll(cx));
+
+            this.idle = None;
+            return Poll::Ready(Err(io::ErrorKind::TimedOut.into()));
+        };
+
+        this.idle = None;
+        Poll::Ready(result)
+    }
+}
+
+impl RequestBody {
+    fn poll_read_body(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match &mut self.kind {
+            BodyKind::Empty => Poll::Ready(Ok(())),
+            BodyKind::Buffered(bytes) => {
+                let n = bytes.len().min(buf.remaining());
+                buf.put_slice(&bytes.split_to(n));
+                Poll::Ready(Ok(()))
+            }
+            BodyKind::Length(remaining) => {
+                let Some(reader) = self.reader.as_mut() else {
+                    return Poll::Ready(Ok(()));
+                };
+                if *remaining == 0 || buf.remaining() == 0 {
+                    return Poll::Ready(Ok(()));
+                }
+
+                let available = ready!(Pin::new(&mut **reader).poll_fill_buf(cx))?;
+                if available.is_empty() {
+                    return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                }
+
+                let n = (*remaining).min(available.len()).min(buf.remaining());
+                buf.put_slice(&available[..n]);
+                Pin::new(&mut **reader).consume(n);
+                *remaining -= n;
+
+                Poll::Ready(Ok(()))
+            }
+            BodyKind::Chunked(decoder) => {
+                let Some(reader) = self.reader.as_mut() else {
+                    return Poll::Ready(Ok(()));
+                };
+
+                decoder
+                    .poll_read(cx, &mut **reader, buf)
+                    .map_err(io::Error::from)
+            }
+        }
+    }
+}
+
+impl Stream for RequestBody {
+    type Item = Result<Bytes, HttpParseError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        if let BodyKind::Buffered(bytes) = &mut this.kind {
+            let bytes = std::mem::take(bytes);
+            return Poll::Ready((!bytes.is_empty()).then_some(Ok(bytes)));
+        }
+
+        let mut buf = std::mem::take(&mut this.buf);
+        buf.resize(CHUNK_SIZE, 0);
+
+        let mut read_buf = ReadBuf::new(&mut buf);
+        let result = Pin::new(&mut *this).poll_read(cx, &mut read_buf);
+        let n = read_buf.filled().len();
+        buf.truncate(n);
+        let chunk = buf.split().freeze();
+        this.buf = buf;
+
+        match result {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Err(e)) => Poll::Ready(Some(Err(e.into()))),
+            Poll::Ready(Ok(())) if chunk.is_empty() => Poll::Ready(None),
+            Poll::Ready(Ok(())) => Poll::Ready(Some(Ok(chunk))),
+        }
+    }
+}
+
+impl fmt::Debug for RequestBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            BodyKind::Empty => write!(f, "RequestBody::Empty"),
+            BodyKind::Buffered(bytes) => write!(f, "RequestBody::Buffered({} bytes)", bytes.len()),
+            BodyKind::Length(remaining) => {
+                write!(f, "RequestBody::Length({} bytes left)", remaining)
+            }
+            BodyKind::Chunked(_) => write!(f, "RequestBody::Chunked"),
+        }
+    }
+}
+
+/// Frames of a streaming response body.
+pub type FrameStream = Pin<Box<dyn Stream<Item = io::Result<BodyFrame>> + Send>>;
+
+/// One piece of a response body as it is written out.
+#[derive(Debug)]
+pub enum BodyFrame {
+    Data(Bytes),
+    /// Trailer fields, sent after the last chunk of a chunked response.
+    Trailers(HttpHeaders),
+}
+
+enum ResponseKind {
+    Full(Bytes),
+    Stream(FrameStream),
+}
+
+/// Body of a response. Anything that converts into a byte buffer can be
+/// assigned with `into()`, so text and binary payloads are sent as is.
+/// Streaming bodies have no length up front and go out chunked on HTTP/1.1
+/// and close-delimited on HTTP/1.0.
+pub struct ResponseBody {
+    kind: ResponseKind,
+}
+
+impl ResponseBody {
+    pub fn new() -> Self {
+        Bytes::new().into()
+    }
+
+    /// Streams everything `reader` yields, e.g. a file too large to buffer.
+    pub fn reader<R: AsyncRead + Send + 'static>(reader: R) -> Self {
+        let chunks = stream::unfold(Some(Box::pin(reader)), |reader| async move {
+            let mut reader = reader?;
+            let mut buf = BytesMut::with_capacity(CHUNK_SIZE);
+
+            match reader.read_buf(&mut buf).await {
+                Ok(0) => None,
+                Ok(_) => Some((Ok(buf.freeze()), Some(reader))),
+                Err(e) => Some((Err(e), None)),
+            }
+        });
+
+        Self::stream(chunks)
+    }
+
+    /// Streams the chunks of `stream`. An error ends the response early, so
+    /// the client sees it was cut short.
+    pub fn stream<S: Stream<Item = io::Result<Bytes>> + Send + 'static>(stream: S) -> Self {
+        use futures_util::StreamExt;
+
+        Self::frames(stream.map(|chunk| chunk.map(BodyFrame::Data)))
+    }
+
+    /// Streams `frames`, which may end with trailer fields.
+    pub fn frames<S: Stream<Item = io::Result<BodyFrame>> + Send + 'static>(frames: S) -> Self {
+        ResponseBody {
+            kind: ResponseKind::Stream(Box::pin(frames)),
+        }
+    }
+
+    /// A body fed through the returned sender, holding at most `capacity`
+    /// chunks at a time. The response is only written once the handler
+    /// returns, so the sender belongs in a spawned task.
+    pub fn channel(capacity: usize) -> (BodySender, ResponseBody) {
+        let (tx, rx) = mpsc::channel(capacity);
+        let body = Self::frames(stream::unfold(rx, |mut rx| async move {
+            let frame = rx.recv().await?;
+            Some((frame, rx))
+        }));
+
+        (BodySender { tx }, body)
+    }
+
+    pub fn is_streaming(&self) -> bool {
+        !matches!(self.kind, ResponseKind::Full(_))
+    }
+
+    /// The whole body, or `None` if it is streamed.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match &self.kind {
+            ResponseKind::Full(bytes) => Some(bytes),
+            ResponseKind::Stream(_) => None,
+        }
+    }
+
+    /// Appends `data` to a buffered body, e.g. while collecting it piece by
+    /// piece. Streaming bodies are left alone.
+    pub fn extend_from_slice(&mut self, data: &[u8]) {
+        if let ResponseKind::Full(bytes) = &mut self.kind {
+            let mut buf = Vec::from(std::mem::take(bytes));
+            buf.extend_from_slice(data);
+            *bytes = buf.into();
+        }
+    }
+
+    /// Next piece to write, or `None` once the body is done. A buffered body
+    /// comes out as a single frame.
+    pub async fn next_frame(&mut self) -> Option<io::Result<BodyFrame>> {
+        use futures_util::StreamExt;
+
+        match &mut self.kind {
+            ResponseKind::Full(bytes) if bytes.is_empty() => None,
+            ResponseKind::Full(bytes) => Some(Ok(BodyFrame::Data(std::mem::take(bytes)))),
+            ResponseKind::Stream(frames) => frames.next().await,
+        }
+    }
+
+    /// The body as a stream of frames, e.g. to wrap it in an encoder.
+    pub fn into_frames(self) -> FrameStream {
+        match self.kind {
+            ResponseKind::Full(bytes) if bytes.is_empty() => Box::pin(stream::empty()),
+            ResponseKind::Full(bytes) => {
+                Box::pin(stream::once(async { Ok(BodyFrame::Data(bytes)) }))
+            }
+            ResponseKind::Stream(frames) => frames,
+        }
+    }
+}
+
+impl Default for ResponseBody {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Bytes> for ResponseBody {
+    fn from(body: Bytes) -> Self {
+        ResponseBody {
+            kind: ResponseKind::Full(body),
+        }
+    }
+}
+
+impl From<Vec<u8>> for ResponseBody {
+    fn from(body: Vec<u8>) -> Self {
+        Bytes::from(body).into()
+    }
+}
+
+impl From<&[u8]> for ResponseBody {
+    fn from(body: &[u8]) -> Self {
+        Bytes::copy_from_slice(body).into()
+    }
+}
+
+impl From<String> for ResponseBody {
+    fn from(body: String) -> Self {
+        Bytes::from(body).into()
+    }
+}
+
+impl From<&str> for ResponseBody {
+    fn from(body: &str) -> Self {
+        body.as_bytes().into()
+    }
+}
+
+impl PartialEq<[u8]> for ResponseBody {
+    fn eq(&self, other: &[u8]) -> bool {
+        self.as_bytes() == Some(other)
+    }
+}
+
+impl PartialEq<&str> for ResponseBody {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_bytes() == Some(other.as_bytes())
+    }
+}
+
+impl fmt::Debug for ResponseBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ResponseKind::Full(bytes) => write!(f, "ResponseBody::Full({} bytes)", bytes.len()),
+            ResponseKind::Stream(_) => write!(f, "ResponseBody::Stream"),
+        }
+    }
+}
+
+/// Producer side of `ResponseBody::channel`. Dropping it ends the body.
+pub struct BodySender {
+    tx: mpsc::Sender<io::Result<BodyFrame>>,
+}
+
+impl BodySender {
+    /// Queues a chunk, waiting while the channel is full. Fails once the
+    /// connection is gone.
+    pub async fn send(&self, data: impl Into<Bytes>) -> io::Result<()> {
+        self.frame(Ok(BodyFrame::Data(data.into()))).await
+    }
+
+    /// Ends the body with `trailers`. They are only sent on chunked
+    /// responses.
+    pub async fn finish(self, trailers: HttpHeaders) -> io::Result<()> {
+        self.frame(Ok(BodyFrame::Trailers(trailers))).await
+    }
+
+    /// Cuts the response short, so the client can tell it is incomplete.
+    pub async fn abort(self) {
+        let _ = self
+            .frame(Err(io::Error::other("response body aborted")))
+            .await;
+    }
+
+    async fn frame(&self, frame: io::Result<BodyFrame>) -> io::Result<()> {
+        self.tx
+            .send(frame)
+            .await
+            .map_err(|_| io::ErrorKind::BrokenPipe.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn streaming(input: &'static [u8], framing: BodyFraming) -> RequestBody {
+        RequestBody::streaming(Box::new(input), framing, &Limits::default())
+    }
+
+    #[tokio::test]
+    async fn collects_length_delimited_body() {
+        let mut body = streaming(b"hello world", BodyFraming::Length(5));
+
+        assert_eq!(body.collect().await.unwrap(), b"hello");
+        assert_eq!(body.collect().await.unwrap(), b"");
+    }
+
+    #[tokio::test]
+    async fn streams_chunked_body_and_exposes_trailers() {
+        let mut body = streaming(
+            b"3\r\nabc\r\n2\r\nde\r\n0\r\nX-Sum: 5\r\n\r\n",
+            BodyFraming::Chunked,
+        );
+
+        let mut received = Vec::new();
+        while let Some(chunk) = body.chunk().await.unwrap() {
+            received.extend_from_slice(&chunk);
+        }
+
+        assert_eq!(received, b"abcde");
+        assert!(body.trailers().is_some());
+    }
+
+    #[tokio::test]
+    async fn finish_drains_unread_body() {
+        let body = streaming(b"helloGET / HTTP/1.1\r\n", BodyFraming::Length(5));
+
+        let mut reader = body.finish().await.unwrap().unwrap();
+
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).await.unwrap();
+        assert_eq!(rest, "GET / HTTP/1.1\r\n");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn times_out_when_the_body_stalls() {
+        let (_client, conn) = io::duplex(64);
+        let reader = Box::new(io::BufReader::new(conn));
+        let mut body = RequestBody::streaming(reader, BodyFraming::Length(5), &Limits::default());
+
+        let res = body.collect().await;
+
+        assert!(matches!(res, Err(HttpParseError::Io(e)) if e.kind() == io::ErrorKind::TimedOut));
+    }
+
+    #[tokio::test]
+    async fn reports_truncated_body() {
+        let mut body = streaming(b"abc", BodyFraming::Length(5));
+
+        let res = body.collect().await;
+
+        assert!(
+            matches!(res, Err(HttpParseError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof)
+        );
+    }
+
+    #[tokio::test]
+    async fn preserves_chunked_parse_errors() {
+        let mut body = streaming(b"zz\r\n", BodyFraming::Chunked);
+
+        assert!(matches!(
+            body.collect().await,
+            Err(HttpParseError::BadChunk)
+        ));
+    }
+
+    #[tokio::test]
+    async fn signals_continue_on_first_read() {
+        let mut body = streaming(b"hello", BodyFraming::Length(5));
+        let mut rx = body.expect_continue();
+
+        assert!(body.is_awaiting_continue());
+        assert!(rx.try_recv().is_err());
+
+        body.collect().await.unwrap();
+
+        assert!(!body.is_awaiting_continue());
+        assert!(rx.try_recv().is_ok());
+    }
+
+    #[test]
+    fn builds_response_body_from_bytes_and_text() {
+        let png: &[u8] = &[0x89, b'P', b'N', b'G', 0x00, 0xff];
+
+        assert_eq!(ResponseBody::from(png).as_bytes(), Some(png));
+        assert_eq!(ResponseBody::from(png.to_vec()), *png);
+        assert_eq!(ResponseBody::from(String::from("hello")), "hello");
+
+        let mut body = ResponseBody::from("ab");
+        body.extend_from_slice(&[0xff]);
+        assert_eq!(body.as_bytes(), Some(&b"ab\xff"[..]));
+    }
+
+    #[tokio::test]
+    async fn streams_reader_and_channel_bodies() {
+        let mut body = ResponseBody::reader(&b"hello"[..]);
+        assert!(body.is_streaming());
+        assert!(matches!(
+            body.next_frame().await,
+            Some(Ok(BodyFrame::Data(data))) if data == "hello"
+        ));
+        assert!(body.next_frame().await.is_none());
+
+        let (tx, mut body) = ResponseBody::channel(4);
+        tx.send("abc").await.unwrap();
+        tx.finish(HttpHeaders::new()).await.unwrap();
+
+        assert!(matches!(
+            body.next_frame().await,
+            Some(Ok(BodyFrame::Data(_)))
+        ));
+        assert!(matches!(
+            body.next_frame().await,
+            Some(Ok(BodyFrame::Trailers(_)))
+        ));
+        assert!(body.next_frame().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn streams_pieces_from_a_reused_buffer() {
+        let input = std::io::Cursor::new(vec![b'x'; CHUNK_SIZE + 10]);
+        let framing = BodyFraming::Length(CHUNK_SIZE + 10);
+        let mut body = RequestBody::streaming(Box::new(input), framing, &Limits::default());
+
+        let first = body.chunk().await.unwrap().unwrap();
+        assert_eq!(first.len(), CHUNK_SIZE);
+        let allocation = first.as_ptr();
+        drop(first);
+
+        let second = body.chunk().await.unwrap().unwrap();
+        assert_eq!(second.len(), 10);
+        assert_eq!(second.as_ptr(), allocation);
+
+        assert_eq!(body.chunk().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn hands_out_buffered_body_once() {
+        let mut body = RequestBody::from(b"hello".to_vec());
+
+        assert_eq!(body.chunk().await.unwrap().as_deref(), Some(&b"hello"[..]));
+        assert_eq!(body.chunk().await.unwrap(), None);
+    }
+}
@@ -0,0 +1,163 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use super::{
+    error::HttpParseError,
+    headers::{is_token, split_list},
+};
+
+/// `Cache-Control` directives, in the order they were given. Directive names
+/// are lower-cased; unknown directives are kept so they can be written back.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CacheControl {
+    directives: Vec<(String, Option<String>)>,
+}
+
+impl CacheControl {
+    pub fn new() -> Self {
+        CacheControl {
+            directives: Vec::new(),
+        }
+    }
+
+    /// Adds a directive, e.g. `with("max-age", Some("60"))`.
+    pub fn with(mut self, name: &str, value: Option<&str>) -> Self {
+        self.directives
+            .push((name.to_ascii_lowercase(), value.map(str::to_string)));
+        self
+    }
+
+    /// Merges the directives of another `Cache-Control` header.
+    pub fn extend(&mut self, other: CacheControl) {
+        self.directives.extend(other.directives);
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.directives.iter().any(|(n, _)| n == name)
+    }
+
+    /// Argument of the first `name` directive, if it has one.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.directives
+            .iter()
+            .find(|(n, _)| n == name)
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.directives
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_deref()))
+    }
+
+    pub fn no_cache(&self) -> bool {
+        self.has("no-cache")
+    }
+
+    pub fn no_store(&self) -> bool {
+        self.has("no-store")
+    }
+
+    pub fn is_public(&self) -> bool {
+        self.has("public")
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.has("private")
+    }
+
+    pub fn must_revalidate(&self) -> bool {
+        self.has("must-revalidate")
+    }
+
+    pub fn max_age(&self) -> Option<Duration> {
+        self.seconds("max-age")
+    }
+
+    pub fn s_maxage(&self) -> Option<Duration> {
+        self.seconds("s-maxage")
+    }
+
+    fn seconds(&self, name: &str) -> Option<Duration> {
+        self.get(name)
+            .and_then(|value| value.parse().ok())
+            .map(Duration::from_secs)
+    }
+}
+
+impl FromStr for CacheControl {
+    type Err = HttpParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut directives = Vec::new();
+
+        for directive in split_list(s) {
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => {
+                    let value = value.trim();
+                    let value = value
+                        .strip_prefix('"')
+                        .and_then(|v| v.strip_suffix('"'))
+                        .unwrap_or(value);
+                    (name.trim(), Some(value.to_string()))
+                }
+                None => (directive, None),
+            };
+
+            if !is_token(name) {
+                return Err(HttpParseError::BadHeader);
+            }
+
+            directives.push((name.to_ascii_lowercase(), value));
+        }
+
+        Ok(CacheControl { directives })
+    }
+}
+
+impl fmt::Display for CacheControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, value)) in self.directives.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            match value {
+                Some(value) if is_token(value) => write!(f, "{}={}", name, value)?,
+                Some(value) => write!(f, "{}=\"{}\"", name, value)?,
+                None => write!(f, "{}", name)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_directives() {
+        let cc = "No-Cache, max-age=60, private=\"Set-Cookie, Vary\", s-maxage=x"
+            .parse::<CacheControl>()
+            .unwrap();
+
+        assert!(cc.no_cache());
+        assert!(cc.is_private());
+        assert!(!cc.no_store());
+        assert_eq!(cc.max_age(), Some(Duration::from_secs(60)));
+        assert_eq!(cc.s_maxage(), None);
+    }
+
+    #[test]
+    fn formats_directives() {
+        let cc = CacheControl::new()
+            .with("public", None)
+            .with("max-age", Some("3600"))
+            .with("no-cache", Some("Set-Cookie, Vary"));
+
+        assert_eq!(
+            cc.to_string(),
+            "public, max-age=3600, no-cache=\"Set-Cookie, Vary\""
+        );
+    }
+}
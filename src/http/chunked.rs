@@ -0,0 +1,451 @@
+use std::{
+    future::poll_fn,
+    pin::Pin,
+    str::FromStr,
+    task::{Context, Poll, ready},
+};
+
+use tokio::io::{self, AsyncBufRead, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+use super::{
+    error::HttpParseError,
+    headers::{HttpHeaderName, HttpHeaders, is_token},
+    limits::Limits,
+    line::poll_read_line,
+    request::parse_header_line,
+};
+
+/// Longest accepted chunk size line, extensions included.
+const MAX_CHUNK_LINE: usize = 4096;
+
+/// The size line preceding every chunk, e.g. `1a;name=value`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ChunkHeader {
+    pub size: usize,
+    pub extensions: Vec<(String, Option<String>)>,
+}
+
+impl FromStr for ChunkHeader {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.strip_suffix("\r\n").unwrap_or(s).split(';');
+
+        // No whitespace around the size: a lenient parser here is one that
+        // disagrees with a stricter proxy about where the chunk ends.
+        let size_str = parts.next().unwrap_or("");
+        if size_str.is_empty() || !size_str.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err("Invalid chunk size");
+        }
+        let size = usize::from_str_radix(size_str, 16).map_err(|_| "Chunk size too large")?;
+
+        let mut extensions = Vec::new();
+        for ext in parts {
+            let mut kv = ext.splitn(2, '=');
+            let name = kv.next().unwrap_or("").trim_matches([' ', '\t']);
+            if !is_token(name) {
+                return Err("Invalid chunk extension");
+            }
+            let value = match kv.next().map(|v| v.trim_matches([' ', '\t'])) {
+                Some(v) if is_token(v) => Some(v.to_string()),
+                Some(v) if is_quoted_string(v) => Some(v[1..v.len() - 1].to_string()),
+                Some(_) => return Err("Invalid chunk extension"),
+                None => None,
+            };
+            extensions.push((name.to_string(), value));
+        }
+
+        Ok(ChunkHeader { size, extensions })
+    }
+}
+
+fn is_quoted_string(s: &str) -> bool {
+    s.len() >= 2
+        && s.starts_with('"')
+        && s.ends_with('"')
+        && s[1..s.len() - 1]
+            .bytes()
+            .all(|b| b == b'\t' || ((b' '..=b'~').contains(&b) && b != b'"' && b != b'\\'))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DecoderState {
+    Size,
+    Data(usize),
+    DataEnd,
+    Trailers,
+    Done,
+}
+
+/// Incremental `Transfer-Encoding: chunked` decoder. It does not own the
+/// reader, so the same decoder serves both streaming and buffered bodies.
+#[derive(Debug)]
+pub struct ChunkedDecoder {
+    state: DecoderState,
+    line: Vec<u8>,
+    limits: Limits,
+    body_len: usize,
+    trailer_bytes: usize,
+    trailer_count: usize,
+    trailers: HttpHeaders,
+}
+
+impl ChunkedDecoder {
+    pub fn new(limits: &Limits) -> Self {
+        ChunkedDecoder {
+            state: DecoderState::Size,
+            line: Vec::new(),
+            limits: *limits,
+            body_len: 0,
+            trailer_bytes: 0,
+            trailer_count: 0,
+            trailers: HttpHeaders::new(),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.state == DecoderState::Done
+    }
+
+    /// Trailer fields received after the last chunk, except for the ones
+    /// that control message framing.
+    pub fn trailers(&self) -> &HttpHeaders {
+        &self.trailers
+    }
+
+    pub fn take_trailers(&mut self) -> HttpHeaders {
+        std::mem::take(&mut self.trailers)
+    }
+
+    /// Decodes as much chunk data into `buf` as is available. Leaves `buf`
+    /// untouched once the terminating chunk and trailers have been read.
+    pub fn poll_read<R: AsyncBufRead + Unpin + ?Sized>(
+        &mut self,
+        cx: &mut Context<'_>,
+        reader: &mut R,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<(), HttpParseError>> {
+        loop {
+            match self.state {
+                DecoderState::Done => return Poll::Ready(Ok(())),
+                DecoderState::Data(remaining) => {
+                    if buf.remaining() == 0 {
+                        return Poll::Ready(Ok(()));
+                    }
+
+                    let available = ready!(Pin::new(&mut *reader).poll_fill_buf(cx))?;
+                    if available.is_empty() {
+                        return Poll::Ready(Err(unexpected_eof()));
+                    }
+
+                    let n = remaining.min(available.len()).min(buf.remaining());
+                    buf.put_slice(&available[..n]);
+                    Pin::new(&mut *reader).consume(n);
+
+                    self.state = if n == remaining {
+                        DecoderState::DataEnd
+                    } else {
+                        DecoderState::Data(remaining - n)
+                    };
+
+                    return Poll::Ready(Ok(()));
+                }
+                DecoderState::Size => {
+                    let line = ready!(self.poll_line(
+                        cx,
+                        reader,
+                        MAX_CHUNK_LINE,
+                        HttpParseError::BadChunk
+                    ))?;
+                    let chunk = line
+                        .parse::<ChunkHeader>()
+                        .map_err(|_| HttpParseError::BadChunk)?;
+
+                    if chunk.size == 0 {
+                        self.state = DecoderState::Trailers;
+                        continue;
+                    }
+
+                    if chunk.size > self.limits.max_body_size - self.body_len {
+                        return Poll::Ready(Err(HttpParseError::BodyTooLarge));
+                    }
+
+                    self.body_len += chunk.size;
+                    self.state = DecoderState::Data(chunk.size);
+                }
+                DecoderState::DataEnd => {
+                    let line = ready!(self.poll_line(cx, reader, 2, HttpParseError::BadChunk))?;
+                    if line != "\r\n" {
+                        return Poll::Ready(Err(HttpParseError::BadChunk));
+                    }
+
+                    self.state = DecoderState::Size;
+                }
+                DecoderState::Trailers => {
+                    let remaining = self
+                        .limits
+                        .max_header_bytes
+                        .saturating_sub(self.trailer_bytes);
+                    let line = ready!(self.poll_line(
+                        cx,
+                        reader,
+                        remaining,
+                        HttpParseError::HeaderTooLarge
+                    ))?;
+
+                    if line == "\r\n" {
+                        self.state = DecoderState::Done;
+                        continue;
+                    }
+
+                    self.trailer_bytes += line.len();
+                    self.trailer_count += 1;
+                    if self.trailer_count > self.limits.max_header_count {
+                        return Poll::Ready(Err(HttpParseError::HeaderTooLarge));
+                    }
+
+                    let (name, value) =
+                        parse_header_line(&line).ok_or(HttpParseError::BadHeader)?;
+
+                    match name {
+                        HttpHeaderName::ContentLength
+                        | HttpHeaderName::TransferEncoding
+                        | HttpHeaderName::Trailer
+                        | HttpHeaderName::Host => {}
+                        _ => self.trailers.append(name, value),
+                    }
+                }
+            }
+        }
+    }
+
+    fn poll_line<R: AsyncBufRead + Unpin + ?Sized>(
+        &mut self,
+        cx: &mut Context<'_>,
+        reader: &mut R,
+        limit: usize,
+        overflow: HttpParseError,
+    ) -> Poll<Result<String, HttpParseError>> {
+        match ready!(poll_read_line(cx, reader, &mut self.line, limit))? {
+            Some(0) => Poll::Ready(Err(unexpected_eof())),
+            Some(_) => {
+                let line = String::from_utf8_lossy(&self.line).into_owned();
+                self.line.clear();
+                Poll::Ready(Ok(line))
+            }
+            None => Poll::Ready(Err(overflow)),
+        }
+    }
+}
+
+fn unexpected_eof() -> HttpParseError {
+    io::Error::from(io::ErrorKind::UnexpectedEof).into()
+}
+
+/// Decodes a whole `Transfer-Encoding: chunked` body. Trailer fields are
+/// merged into `headers`.
+pub async fn read_chunked_body<R: AsyncBufRead + Unpin + ?Sized>(
+    reader: &mut R,
+    headers: &mut HttpHeaders,
+    limits: &Limits,
+) -> Result<Vec<u8>, HttpParseError> {
+    let mut decoder = ChunkedDecoder::new(limits);
+    let mut body = Vec::new();
+    let mut buf = vec![0u8; 8 * 1024];
+
+    while !decoder.is_done() {
+        let n = poll_fn(|cx| {
+            let mut read_buf = ReadBuf::new(&mut buf);
+            ready!(decoder.poll_read(cx, reader, &mut read_buf))?;
+            Poll::Ready(Ok::<_, HttpParseError>(read_buf.filled().len()))
+        })
+        .await?;
+
+        body.extend_from_slice(&buf[..n]);
+    }
+
+    headers.extend(decoder.take_trailers());
+
+    Ok(body)
+}
+
+/// Writes `data` as one chunk. Empty data is skipped, since a zero-size
+/// chunk would end the body.
+pub async fn write_chunk<W: AsyncWrite + Unpin + ?Sized>(
+    writer: &mut W,
+    data: &[u8],
+) -> io::Result<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+
+    let mut chunk = format!("{:x}\r\n", data.len()).into_bytes();
+    chunk.extend_from_slice(data);
+    chunk.extend_from_slice(b"\r\n");
+
+    writer.write_all(&chunk).await
+}
+
+/// Ends a chunked body with the zero-size chunk and the trailer section.
+pub async fn write_last_chunk<W: AsyncWrite + Unpin + ?Sized>(
+    writer: &mut W,
+    trailers: &HttpHeaders,
+) -> io::Result<()> {
+    let mut last = String::from("0\r\n");
+    if !trailers.is_empty() {
+        last.push_str(&trailers.as_str());
+        last.push_str("\r\n");
+    }
+    last.push_str("\r\n");
+
+    writer.write_all(last.as_bytes()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn decode(input: &[u8]) -> (Result<Vec<u8>, HttpParseError>, HttpHeaders) {
+        let mut reader = input;
+        let mut headers = HttpHeaders::new();
+        let body = read_chunked_body(&mut reader, &mut headers, &Limits::default()).await;
+        (body, headers)
+    }
+
+    #[test]
+    fn parses_chunk_header_with_extensions() {
+        let header = "1A;foo=bar;baz;q=\"x y\"\r\n"
+            .parse::<ChunkHeader>()
+            .unwrap();
+
+        assert_eq!(header.size, 26);
+        assert_eq!(
+            header.extensions,
+            vec![
+                ("foo".to_string(), Some("bar".to_string())),
+                ("baz".to_string(), None),
+                ("q".to_string(), Some("x y".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_chunk_size() {
+        assert!("zz\r\n".parse::<ChunkHeader>().is_err());
+        assert!("\r\n".parse::<ChunkHeader>().is_err());
+        assert!("-1\r\n".parse::<ChunkHeader>().is_err());
+        assert!("ffffffffffffffffff\r\n".parse::<ChunkHeader>().is_err());
+        assert!(" 5\r\n".parse::<ChunkHeader>().is_err());
+        assert!("5 \r\n".parse::<ChunkHeader>().is_err());
+        assert!("5\t;ext\r\n".parse::<ChunkHeader>().is_err());
+        assert!("5\n".parse::<ChunkHeader>().is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_chunk_extensions() {
+        assert!("5;\r\n".parse::<ChunkHeader>().is_err());
+        assert!("5;a b\r\n".parse::<ChunkHeader>().is_err());
+        assert!("5;(x)=1\r\n".parse::<ChunkHeader>().is_err());
+        assert!("5;x=a b\r\n".parse::<ChunkHeader>().is_err());
+        assert!("5;x=\"open\r\n".parse::<ChunkHeader>().is_err());
+        assert!("5; x = 1\r\n".parse::<ChunkHeader>().is_ok());
+    }
+
+    #[tokio::test]
+    async fn decodes_multiple_chunks() {
+        let (body, _) =
+            decode(b"4\r\nWiki\r\n5;ext=1\r\npedia\r\nE\r\n in\r\n\r\nchunks.\r\n0\r\n\r\n").await;
+
+        assert_eq!(body.unwrap(), b"Wikipedia in\r\n\r\nchunks.");
+    }
+
+    #[tokio::test]
+    async fn decodes_zero_length_body() {
+        let (body, _) = decode(b"0\r\n\r\n").await;
+
+        assert_eq!(body.unwrap(), b"");
+    }
+
+    #[tokio::test]
+    async fn leaves_next_request_unread() {
+        let mut reader: &[u8] = b"3\r\nabc\r\n0\r\n\r\nGET / HTTP/1.1\r\n";
+        let mut headers = HttpHeaders::new();
+
+        let body = read_chunked_body(&mut reader, &mut headers, &Limits::default())
+            .await
+            .unwrap();
+
+        assert_eq!(body, b"abc");
+        assert_eq!(reader, b"GET / HTTP/1.1\r\n");
+    }
+
+    #[tokio::test]
+    async fn merges_trailers_into_headers() {
+        let (body, headers) =
+            decode(b"3\r\nabc\r\n0\r\nX-Checksum: 123\r\nContent-Length: 99\r\n\r\n").await;
+
+        assert_eq!(body.unwrap(), b"abc");
+        assert_eq!(
+            headers.get_one_raw(&HttpHeaderName::from("X-Checksum")),
+            Some("123")
+        );
+        assert_eq!(headers.content_length(), None);
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_crlf_after_data() {
+        let (body, _) = decode(b"3\r\nabcd\r\n0\r\n\r\n").await;
+        assert!(matches!(body, Err(HttpParseError::BadChunk)));
+
+        let (body, _) = decode(b"3\r\nabc\n0\r\n\r\n").await;
+        assert!(matches!(body, Err(HttpParseError::BadChunk)));
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_trailer() {
+        let (body, _) = decode(b"0\r\nX-Checksum 123\r\n\r\n").await;
+
+        assert!(matches!(body, Err(HttpParseError::BadHeader)));
+    }
+
+    #[tokio::test]
+    async fn rejects_body_over_limit() {
+        let mut reader: &[u8] = b"4\r\nWiki\r\n5\r\npedia\r\n0\r\n\r\n";
+        let mut headers = HttpHeaders::new();
+        let limits = Limits {
+            max_body_size: 8,
+            ..Limits::default()
+        };
+
+        let body = read_chunked_body(&mut reader, &mut headers, &limits).await;
+
+        assert!(matches!(body, Err(HttpParseError::BodyTooLarge)));
+    }
+
+    #[tokio::test]
+    async fn rejects_truncated_body() {
+        let (body, _) = decode(b"a\r\nabc").await;
+
+        assert!(
+            matches!(body, Err(HttpParseError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof)
+        );
+    }
+
+    #[tokio::test]
+    async fn encodes_what_it_decodes() {
+        let mut trailers = HttpHeaders::new();
+        trailers.append(HttpHeaderName::from("X-Sum"), "5");
+
+        let mut out = Vec::new();
+        write_chunk(&mut out, b"abc").await.unwrap();
+        write_chunk(&mut out, b"").await.unwrap();
+        write_chunk(&mut out, b"de").await.unwrap();
+        write_last_chunk(&mut out, &trailers).await.unwrap();
+
+        assert_eq!(out, b"3\r\nabc\r\n2\r\nde\r\n0\r\nX-Sum: 5\r\n\r\n");
+
+        let (body, headers) = decode(&out).await;
+        assert_eq!(body.unwrap(), b"abcde");
+        assert_eq!(headers.len(), 1);
+    }
+}
@@ -0,0 +1,404 @@
+use std::io::{self, Write};
+
+use brotli::CompressorWriter;
+use flate2::write::{GzEncoder, ZlibEncoder};
+use futures_util::{StreamExt, stream};
+use log::error;
+
+use super::{
+    body::{BodyFrame, FrameStream, ResponseBody},
+    headers::{HttpHeaderName, HttpHeaders},
+    response::HttpResponse,
+    status::StatusCode,
+};
+
+/// Media types that are compressed already, so encoding them again only
+/// costs CPU. `image/svg+xml` is text and stays eligible.
+const COMPRESSED_TYPES: [&str; 16] = [
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/avif",
+    "audio/*",
+    "video/*",
+    "font/woff",
+    "font/woff2",
+    "application/zip",
+    "application/gzip",
+    "application/x-gzip",
+    "application/x-bzip2",
+    "application/x-7z-compressed",
+    "application/zstd",
+    "application/pdf",
+];
+
+/// A content coding the server can apply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentCoding {
+    Brotli,
+    Gzip,
+    /// The zlib format, which is what HTTP calls `deflate`.
+    Deflate,
+}
+
+impl ContentCoding {
+    /// In order of preference when the client weighs them the same.
+    const ALL: [ContentCoding; 3] = [
+        ContentCoding::Brotli,
+        ContentCoding::Gzip,
+        ContentCoding::Deflate,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentCoding::Brotli => "br",
+            ContentCoding::Gzip => "gzip",
+            ContentCoding::Deflate => "deflate",
+        }
+    }
+
+    /// The coding the client prefers according to `Accept-Encoding`, or
+    /// `None` if it sent none or accepts none of them.
+    pub fn negotiate(request_headers: &HttpHeaders) -> Option<ContentCoding> {
+        let accepted = request_headers.accept_encoding()?;
+        let names = Self::ALL.map(|coding| coding.as_str());
+        let chosen = accepted.negotiate(&names)?;
+
+        Self::ALL
+            .into_iter()
+            .find(|coding| coding.as_str() == chosen)
+    }
+}
+
+/// Settings for compressing responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+    /// Buffered bodies below this many bytes are sent as is, since the
+    /// coding overhead outweighs the savings.
+    pub min_size: usize,
+    /// 0-9 for gzip and deflate. Brotli uses the same value as its quality.
+    pub level: u32,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression {
+            min_size: 1024,
+            level: 6,
+        }
+    }
+}
+
+impl Compression {
+    /// Encodes `res` with the coding negotiated from the request headers.
+    /// Responses that could be encoded get `Vary: Accept-Encoding` even when
+    /// this client did not ask for it, so caches keep the variants apart.
+    pub fn apply(&self, request_headers: &HttpHeaders, res: &mut HttpResponse) {
+        if !self.is_eligible(res) {
+            return;
+        }
+
+        add_vary(&mut res.headers);
+
+        let Some(coding) = ContentCoding::negotiate(request_headers) else {
+            return;
+        };
+
+        match res.body.as_bytes() {
+            Some(body) => match self.encode(coding, body) {
+                // Keep the original if encoding did not make it any smaller.
+                Ok(encoded) if encoded.len() < body.len() => res.body = encoded.into(),
+                Ok(_) => return,
+                Err(e) => {
+                    error!("Failed to compress response body: {}", e);
+                    return;
+                }
+            },
+            None => {
+                let frames = std::mem::take(&mut res.body).into_frames();
+                res.body = ResponseBody::frames(self.encode_stream(coding, frames));
+            }
+        }
+
+        res.add_header(HttpHeaderName::ContentEncoding, coding.as_str());
+    }
+
+    fn is_eligible(&self, res: &HttpResponse) -> bool {
+        let status = res.status_code;
+        if !status.is_success() && !status.is_client_error() && !status.is_server_error() {
+            return false;
+        }
+        // Ranges refer to the unencoded bytes.
+        if matches!(status, StatusCode::NO_CONTENT | StatusCode::PARTIAL_CONTENT) {
+            return false;
+        }
+        if res.headers.contains(&HttpHeaderName::ContentEncoding) {
+            return false;
+        }
+        if res
+            .body
+            .as_bytes()
+            .is_some_and(|body| body.len() < self.min_size)
+        {
+            return false;
+        }
+
+        !res.headers.content_type().is_some_and(is_compressed_type)
+    }
+
+    fn encode(&self, coding: ContentCoding, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut encoder = Encoder::new(coding, self.level);
+        encoder.write_all(data)?;
+
+        encoder.finish()
+    }
+
+    /// Encodes the data frames of `frames`, flushing after each one so
+    /// streamed output is not held back. Trailers follow the encoded data.
+    fn encode_stream(&self, coding: ContentCoding, frames: FrameStream) -> FrameStream {
+        let state = Some((frames, Encoder::new(coding, self.level), HttpHeaders::new()));
+
+        let encoded = stream::unfold(state, |state| async move {
+            let (mut frames, mut encoder, mut trailers) = state?;
+
+            loop {
+                match frames.next().await {
+                    Some(Ok(BodyFrame::Data(data))) => {
+                        match encoder.write_all(&data).and_then(|()| encoder.flush()) {
+                            Ok(chunk) if chunk.is_empty() => {}
+                            Ok(chunk) => {
+                                let frame = Ok(BodyFrame::Data(chunk.into()));
+                                return Some((vec![frame], Some((frames, encoder, trailers))));
+                            }
+                            Err(e) => return Some((vec![Err(e)], None)),
+                        }
+                    }
+                    Some(Ok(BodyFrame::Trailers(more))) => trailers.extend(more),
+                    Some(Err(e)) => return Some((vec![Err(e)], None)),
+                    None => break,
+                }
+            }
+
+            let mut last = vec![encoder.finish().map(|data| BodyFrame::Data(data.into()))];
+            if !trailers.is_empty() {
+                last.push(Ok(BodyFrame::Trailers(trailers)));
+            }
+
+            Some((last, None))
+        });
+
+        Box::pin(encoded.flat_map(stream::iter))
+    }
+}
+
+enum Encoder {
+    Brotli(Box<CompressorWriter<Vec<u8>>>),
+    Gzip(GzEncoder<Vec<u8>>),
+    Deflate(ZlibEncoder<Vec<u8>>),
+}
+
+impl Encoder {
+    fn new(coding: ContentCoding, level: u32) -> Self {
+        let level = level.min(9);
+
+        match coding {
+            ContentCoding::Brotli => {
+                Encoder::Brotli(Box::new(CompressorWriter::new(Vec::new(), 4096, level, 22)))
+            }
+            ContentCoding::Gzip => {
+                Encoder::Gzip(GzEncoder::new(Vec::new(), flate2::Compression::new(level)))
+            }
+            ContentCoding::Deflate => Encoder::Deflate(ZlibEncoder::new(
+                Vec::new(),
+                flate2::Compression::new(level),
+            )),
+        }
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        match self {
+            Encoder::Brotli(w) => w.write_all(data),
+            Encoder::Gzip(w) => w.write_all(data),
+            Encoder::Deflate(w) => w.write_all(data),
+        }
+    }
+
+    /// Flushes what has been written so far and takes the encoded output.
+    fn flush(&mut self) -> io::Result<Vec<u8>> {
+        let output = match self {
+            Encoder::Brotli(w) => {
+                w.flush()?;
+                w.get_mut()
+            }
+            Encoder::Gzip(w) => {
+                w.flush()?;
+                w.get_mut()
+            }
+            Encoder::Deflate(w) => {
+                w.flush()?;
+                w.get_mut()
+            }
+        };
+
+        Ok(std::mem::take(output))
+    }
+
+    /// Ends the encoded stream and returns the output not taken yet.
+    fn finish(self) -> io::Result<Vec<u8>> {
+        match self {
+            Encoder::Brotli(w) => Ok(w.into_inner()),
+            Encoder::Gzip(w) => w.finish(),
+            Encoder::Deflate(w) => w.finish(),
+        }
+    }
+}
+
+fn is_compressed_type(content_type: &str) -> bool {
+    let media_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+
+    COMPRESSED_TYPES
+        .iter()
+        .any(|compressed| match compressed.strip_suffix("/*") {
+            Some(prefix) => media_type
+                .split_once('/')
+                .is_some_and(|(kind, _)| kind == prefix),
+            None => media_type == *compressed,
+        })
+}
+
+/// Adds `Accept-Encoding` to `Vary` unless it is listed already.
+fn add_vary(headers: &mut HttpHeaders) {
+    let listed = headers.get_all(&HttpHeaderName::Vary).any(|value| {
+        value
+            .as_str()
+            .split(',')
+            .map(str::trim)
+            .any(|name| name == "*" || name.eq_ignore_ascii_case("accept-encoding"))
+    });
+
+    if !listed {
+        headers.append(HttpHeaderName::Vary, "Accept-Encoding");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use bytes::Bytes;
+    use flate2::read::{GzDecoder, ZlibDecoder};
+
+    use super::*;
+
+    fn request(accept_encoding: &str) -> HttpHeaders {
+        let mut headers = HttpHeaders::new();
+        headers.append(HttpHeaderName::AcceptEncoding, accept_encoding);
+        headers
+    }
+
+    fn decode(coding: ContentCoding, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        match coding {
+            ContentCoding::Brotli => brotli::Decompressor::new(data, 4096).read_to_end(&mut out),
+            ContentCoding::Gzip => GzDecoder::new(data).read_to_end(&mut out),
+            ContentCoding::Deflate => ZlibDecoder::new(data).read_to_end(&mut out),
+        }
+        .unwrap();
+        out
+    }
+
+    #[test]
+    fn negotiates_by_quality() {
+        let pick = |value| ContentCoding::negotiate(&request(value));
+
+        assert_eq!(pick("gzip;q=0.5, br;q=0.8"), Some(ContentCoding::Brotli));
+        assert_eq!(pick("deflate, gzip"), Some(ContentCoding::Gzip));
+        assert_eq!(pick("br;q=0, *"), Some(ContentCoding::Gzip));
+        assert_eq!(pick("deflate"), Some(ContentCoding::Deflate));
+        assert_eq!(pick("identity"), None);
+        assert_eq!(ContentCoding::negotiate(&HttpHeaders::new()), None);
+    }
+
+    #[test]
+    fn compresses_buffered_bodies() {
+        let body = "hello compression ".repeat(200);
+
+        for coding in ContentCoding::ALL {
+            let mut res = HttpResponse::text(body.clone());
+            Compression::default().apply(&request(coding.as_str()), &mut res);
+
+            assert_eq!(
+                res.headers.get_one_raw(&HttpHeaderName::ContentEncoding),
+                Some(coding.as_str())
+            );
+            assert_eq!(
+                res.headers.get_one_raw(&HttpHeaderName::Vary),
+                Some("Accept-Encoding")
+            );
+
+            let encoded = res.body.as_bytes().unwrap();
+            assert!(encoded.len() < body.len());
+            assert_eq!(decode(coding, encoded), body.as_bytes());
+        }
+    }
+
+    #[test]
+    fn skips_small_and_compressed_bodies() {
+        let gzip = request("gzip");
+
+        let mut res = HttpResponse::text("tiny".to_string());
+        Compression::default().apply(&gzip, &mut res);
+        assert!(!res.headers.contains(&HttpHeaderName::ContentEncoding));
+        assert!(!res.headers.contains(&HttpHeaderName::Vary));
+
+        let mut res = HttpResponse::new()
+            .with_header(HttpHeaderName::ContentType, "image/png")
+            .with_header(HttpHeaderName::Vary, "accept-encoding");
+        res.body = vec![0u8; 4096].into();
+        Compression::default().apply(&gzip, &mut res);
+        assert!(!res.headers.contains(&HttpHeaderName::ContentEncoding));
+
+        let mut res = HttpResponse::text("x".repeat(4096));
+        Compression::default().apply(&HttpHeaders::new(), &mut res);
+        assert!(!res.headers.contains(&HttpHeaderName::ContentEncoding));
+        assert!(res.headers.contains(&HttpHeaderName::Vary));
+    }
+
+    #[tokio::test]
+    async fn compresses_streaming_bodies_and_keeps_trailers() {
+        let mut trailers = HttpHeaders::new();
+        trailers.append(HttpHeaderName::from("X-Sum"), "5");
+
+        let frames = vec![
+            Ok(BodyFrame::Data(Bytes::from("hel"))),
+            Ok(BodyFrame::Data(Bytes::from("lo"))),
+            Ok(BodyFrame::Trailers(trailers)),
+        ];
+        let mut res = HttpResponse::new();
+        res.body = ResponseBody::frames(stream::iter(frames));
+
+        Compression::default().apply(&request("gzip"), &mut res);
+        assert!(res.body.is_streaming());
+
+        let mut encoded = Vec::new();
+        let mut received_trailers = None;
+        while let Some(frame) = res.body.next_frame().await {
+            match frame.unwrap() {
+                BodyFrame::Data(data) => {
+                    assert!(received_trailers.is_none());
+                    encoded.extend_from_slice(&data);
+                }
+                BodyFrame::Trailers(trailers) => received_trailers = Some(trailers),
+            }
+        }
+
+        assert_eq!(decode(ContentCoding::Gzip, &encoded), b"hello");
+        assert_eq!(received_trailers.map(|t| t.len()), Some(1));
+    }
+}
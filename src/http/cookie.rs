@@ -0,0 +1,236 @@
+use std::{fmt, time::Duration};
+
+use super::headers::is_token;
+
+/// Cookies sent by the client in `Cookie` headers, in the order they
+/// appeared.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CookieJar {
+    cookies: Vec<(String, String)>,
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        CookieJar {
+            cookies: Vec::new(),
+        }
+    }
+
+    /// Adds the `name=value` pairs of one `Cookie` header. Pairs without a
+    /// name are skipped rather than failing the whole header.
+    pub fn parse_header(&mut self, value: &str) {
+        for pair in value.split(';') {
+            let Some((name, value)) = pair.split_once('=') else {
+                continue;
+            };
+
+            let name = name.trim();
+            if !is_token(name) {
+                continue;
+            }
+
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+
+            self.cookies.push((name.to_string(), value.to_string()));
+        }
+    }
+
+    /// Value of the first cookie called `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.cookies
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.cookies.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    /// Browsers only accept this together with `secure`.
+    None,
+}
+
+impl SameSite {
+    pub fn as_str(&self) -> &str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+}
+
+/// A cookie to send in a `Set-Cookie` header, built up with chained calls:
+/// `Cookie::new("id", "42").http_only().max_age(Duration::from_secs(3600))`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    pub fn new(name: &str, value: &str) -> Self {
+        Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            path: None,
+            domain: None,
+            max_age: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    pub fn domain(mut self, domain: &str) -> Self {
+        self.domain = Some(domain.to_string());
+        self
+    }
+
+    /// How long the client keeps the cookie. Zero deletes it.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn secure(mut self) -> Self {
+        self.secure = true;
+        self
+    }
+
+    pub fn http_only(mut self) -> Self {
+        self.http_only = true;
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Whether the cookie can be sent as is: the name must be a token and
+    /// neither the value nor the attributes may contain characters that
+    /// would end the attribute or the header line.
+    pub fn is_valid(&self) -> bool {
+        let is_attr = |s: &str| !s.bytes().any(|b| b == b';' || b.is_ascii_control());
+
+        is_token(&self.name)
+            && self.value.bytes().all(is_cookie_octet)
+            && self.path.as_deref().is_none_or(is_attr)
+            && self.domain.as_deref().is_none_or(is_attr)
+    }
+}
+
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site.as_str())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// RFC 6265 `cookie-octet`: printable ASCII except whitespace, `"`, `,`,
+/// `;` and `\`.
+fn is_cookie_octet(b: u8) -> bool {
+    matches!(b, 0x21 | 0x23..=0x2B | 0x2D..=0x3A | 0x3C..=0x5B | 0x5D..=0x7E)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cookie_header() {
+        let mut jar = CookieJar::new();
+        jar.parse_header("session=abc123; theme=\"dark\";flag=; =orphan; junk");
+        jar.parse_header("session=second");
+
+        assert_eq!(jar.get("session"), Some("abc123"));
+        assert_eq!(jar.get("theme"), Some("dark"));
+        assert_eq!(jar.get("flag"), Some(""));
+        assert_eq!(jar.get("junk"), None);
+        assert_eq!(jar.len(), 4);
+    }
+
+    #[test]
+    fn formats_set_cookie_value() {
+        let cookie = Cookie::new("id", "42")
+            .path("/")
+            .domain("example.com")
+            .max_age(Duration::from_secs(3600))
+            .secure()
+            .http_only()
+            .same_site(SameSite::Lax);
+
+        assert_eq!(
+            cookie.to_string(),
+            "id=42; Path=/; Domain=example.com; Max-Age=3600; Secure; HttpOnly; SameSite=Lax"
+        );
+        assert_eq!(Cookie::new("a", "b").to_string(), "a=b");
+    }
+
+    #[test]
+    fn validates_cookie() {
+        assert!(Cookie::new("id", "abc-_.~").is_valid());
+        assert!(!Cookie::new("id", "a b").is_valid());
+        assert!(!Cookie::new("id", "a;b").is_valid());
+        assert!(!Cookie::new("i d", "a").is_valid());
+        assert!(!Cookie::new("id", "a").path("/\r\nX-Evil: 1").is_valid());
+    }
+}
@@ -0,0 +1,314 @@
+use std::{
+    fmt,
+    str::FromStr,
+    sync::RwLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::error::HttpParseError;
+
+const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const LONG_DAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// The current second's IMF-fixdate, shared by every connection so a busy
+/// server formats its `Date` headers once per second.
+static NOW: DateCache = DateCache::new();
+
+/// An HTTP-date with second precision, as used by `Date`, `Expires`,
+/// `Last-Modified` and the conditional request headers. Formats as
+/// IMF-fixdate and parses the two obsolete formats as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HttpDate {
+    /// Seconds since the Unix epoch.
+    secs: u64,
+}
+
+impl HttpDate {
+    pub fn now() -> Self {
+        SystemTime::now().into()
+    }
+
+    pub fn from_unix_secs(secs: u64) -> Self {
+        HttpDate { secs }
+    }
+
+    pub fn unix_secs(&self) -> u64 {
+        self.secs
+    }
+}
+
+impl From<SystemTime> for HttpDate {
+    /// Truncates to whole seconds. Times before 1970 become the epoch.
+    fn from(time: SystemTime) -> Self {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+
+        HttpDate { secs }
+    }
+}
+
+impl From<HttpDate> for SystemTime {
+    fn from(date: HttpDate) -> Self {
+        UNIX_EPOCH + Duration::from_secs(date.secs)
+    }
+}
+
+impl FromStr for HttpDate {
+    type Err = HttpParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_imf_fixdate(s)
+            .or_else(|| parse_rfc850(s))
+            .or_else(|| parse_asctime(s))
+            .map(|secs| HttpDate { secs })
+            .ok_or(HttpParseError::BadHeader)
+    }
+}
+
+impl fmt::Display for HttpDate {
+    /// IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&imf_fixdate(self.secs))
+    }
+}
+
+/// IMF-fixdate of the current time, for the `Date` response header.
+pub fn now_imf_fixdate() -> String {
+    NOW.format(HttpDate::now())
+}
+
+/// Remembers the last second it formatted. Only ever fed the current time,
+/// so other dates such as `Expires` or `Last-Modified` cannot displace it.
+struct DateCache {
+    latest: RwLock<(u64, String)>,
+}
+
+impl DateCache {
+    const fn new() -> Self {
+        DateCache {
+            latest: RwLock::new((0, String::new())),
+        }
+    }
+
+    fn format(&self, now: HttpDate) -> String {
+        if let Ok(latest) = self.latest.read()
+            && latest.0 == now.secs
+            && !latest.1.is_empty()
+        {
+            return latest.1.clone();
+        }
+
+        let formatted = imf_fixdate(now.secs);
+
+        // Whoever loses the race to update just skips it.
+        if let Ok(mut latest) = self.latest.try_write() {
+            *latest = (now.secs, formatted.clone());
+        }
+
+        formatted
+    }
+}
+
+fn imf_fixdate(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let time = secs % 86400;
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[((days + 4) % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// `Sun, 06 Nov 1994 08:49:37 GMT`
+fn parse_imf_fixdate(s: &str) -> Option<u64> {
+    let (weekday, rest) = s.split_once(", ")?;
+    if !DAYS.contains(&weekday) {
+        return None;
+    }
+
+    match rest.split(' ').collect::<Vec<&str>>()[..] {
+        [day, month, year, time, "GMT"] if day.len() == 2 && year.len() == 4 => {
+            timestamp(year.parse().ok()?, month, day, time)
+        }
+        _ => None,
+    }
+}
+
+/// `Sunday, 06-Nov-94 08:49:37 GMT`
+fn parse_rfc850(s: &str) -> Option<u64> {
+    let (weekday, rest) = s.split_once(", ")?;
+    if !LONG_DAYS.contains(&weekday) {
+        return None;
+    }
+
+    let [date, time, "GMT"] = rest.split(' ').collect::<Vec<&str>>()[..] else {
+        return None;
+    };
+    let [day, month, year] = date.split('-').collect::<Vec<&str>>()[..] else {
+        return None;
+    };
+    if day.len() != 2 || year.len() != 2 {
+        return None;
+    }
+
+    // Two-digit years are read as the closest one that is not too far in
+    // the future, which in practice means 1970-2069.
+    let year: i64 = year.parse().ok()?;
+    let year = if year < 70 { 2000 + year } else { 1900 + year };
+
+    timestamp(year, month, day, time)
+}
+
+/// `Sun Nov  6 08:49:37 1994`
+fn parse_asctime(s: &str) -> Option<u64> {
+    let [weekday, month, day, time, year] = s.split_whitespace().collect::<Vec<&str>>()[..] else {
+        return None;
+    };
+    if !DAYS.contains(&weekday) || year.len() != 4 {
+        return None;
+    }
+
+    timestamp(year.parse().ok()?, month, day, time)
+}
+
+fn timestamp(year: i64, month: &str, day: &str, time: &str) -> Option<u64> {
+    let month = MONTHS.iter().position(|m| *m == month)? as u32 + 1;
+    let day: u32 = day.parse().ok()?;
+
+    let [h, m, s] = time.split(':').collect::<Vec<&str>>()[..] else {
+        return None;
+    };
+    if [h, m, s].iter().any(|part| part.len() != 2) {
+        return None;
+    }
+    let (h, m, s): (u64, u64, u64) = (h.parse().ok()?, m.parse().ok()?, s.parse().ok()?);
+    // 60 is a leap second.
+    if h > 23 || m > 59 || s > 60 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    if days < 0 || civil_from_days(days) != (year, month, day) {
+        return None;
+    }
+
+    Some(days as u64 * 86400 + h * 3600 + m * 60 + s)
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month as i64 + 9) % 12) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_all_three_formats() {
+        let expected = HttpDate::from_unix_secs(784111777);
+
+        for s in [
+            "Sun, 06 Nov 1994 08:49:37 GMT",
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 1994",
+        ] {
+            assert_eq!(s.parse::<HttpDate>().unwrap(), expected, "{}", s);
+        }
+    }
+
+    #[test]
+    fn formats_imf_fixdate() {
+        assert_eq!(
+            HttpDate::from_unix_secs(784111777).to_string(),
+            "Sun, 06 Nov 1994 08:49:37 GMT"
+        );
+        assert_eq!(
+            HttpDate::from_unix_secs(0).to_string(),
+            "Thu, 01 Jan 1970 00:00:00 GMT"
+        );
+        assert_eq!(
+            HttpDate::from_unix_secs(951782400).to_string(),
+            "Tue, 29 Feb 2000 00:00:00 GMT"
+        );
+    }
+
+    #[test]
+    fn caches_current_second() {
+        let cache = DateCache::new();
+        let now = HttpDate::from_unix_secs(784_111_777);
+
+        assert_eq!(cache.format(now), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(cache.latest.read().unwrap().0, now.unix_secs());
+        assert_eq!(cache.format(now), "Sun, 06 Nov 1994 08:49:37 GMT");
+
+        let next = HttpDate::from_unix_secs(784_111_778);
+        assert_eq!(cache.format(next), "Sun, 06 Nov 1994 08:49:38 GMT");
+        assert_eq!(cache.latest.read().unwrap().0, next.unix_secs());
+    }
+
+    #[test]
+    fn round_trips_through_system_time() {
+        let date = HttpDate::from_unix_secs(1_700_000_000);
+        let time: SystemTime = date.into();
+
+        assert_eq!(HttpDate::from(time), date);
+        assert_eq!(date.to_string().parse::<HttpDate>().unwrap(), date);
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        for s in [
+            "",
+            "Sun, 6 Nov 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 08:49:37 UTC",
+            "Sun, 31 Feb 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 24:00:00 GMT",
+            "Sun, 06 Foo 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1969 08:49:37 GMT",
+            "Funday, 06-Nov-94 08:49:37 GMT",
+        ] {
+            assert!(s.parse::<HttpDate>().is_err(), "{}", s);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::{error::Error, fmt, io};
+
+use super::status::StatusCode;
+
+#[derive(Debug)]
+pub enum HttpParseError {
+    Io(io::Error),
+    ConnectionClosed,
+    BadRequestLine,
+    BadHeader,
+    BadChunk,
+    UnsupportedVersion,
+    UriTooLong,
+    HeaderTooLarge,
+    BodyTooLarge,
+    InvalidContentLength,
+    ConflictingFraming,
+    InvalidHost,
+    ExpectationFailed,
+    UnsupportedTransferCoding,
+}
+
+impl HttpParseError {
+    /// Status code to answer the client with, or `None` when the connection
+    /// is unusable and should just be dropped.
+    pub fn status_code(&self) -> Option<StatusCode> {
+        match self {
+            HttpParseError::Io(_) | HttpParseError::ConnectionClosed => None,
+            HttpParseError::BadRequestLine
+            | HttpParseError::BadHeader
+            | HttpParseError::BadChunk
+            | HttpParseError::InvalidContentLength
+            | HttpParseError::ConflictingFraming
+            | HttpParseError::InvalidHost => Some(StatusCode::BAD_REQUEST),
+            HttpParseError::BodyTooLarge => Some(StatusCode::CONTENT_TOO_LARGE),
+            HttpParseError::UriTooLong => Some(StatusCode::URI_TOO_LONG),
+            HttpParseError::ExpectationFailed => Some(StatusCode::EXPECTATION_FAILED),
+            HttpParseError::UnsupportedTransferCoding => Some(StatusCode::NOT_IMPLEMENTED),
+            HttpParseError::HeaderTooLarge => Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE),
+            HttpParseError::UnsupportedVersion => Some(StatusCode::HTTP_VERSION_NOT_SUPPORTED),
+        }
+    }
+}
+
+impl fmt::Display for HttpParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpParseError::Io(e) => write!(f, "I/O error: {}", e),
+            HttpParseError::ConnectionClosed => write!(f, "Connection closed by peer"),
+            HttpParseError::BadRequestLine => write!(f, "Malformed request line"),
+            HttpParseError::BadHeader => write!(f, "Malformed header line"),
+            HttpParseError::BadChunk => write!(f, "Malformed chunked body"),
+            HttpParseError::UnsupportedVersion => write!(f, "HTTP version not supported"),
+            HttpParseError::UriTooLong => write!(f, "Request target too long"),
+            HttpParseError::HeaderTooLarge => write!(f, "Request header fields too large"),
+            HttpParseError::BodyTooLarge => write!(f, "Request body too large"),
+            HttpParseError::InvalidContentLength => write!(f, "Invalid Content-Length"),
+            HttpParseError::ConflictingFraming => write!(f, "Ambiguous message framing"),
+            HttpParseError::InvalidHost => write!(f, "Missing or invalid Host header"),
+            HttpParseError::ExpectationFailed => write!(f, "Unsupported expectation"),
+            HttpParseError::UnsupportedTransferCoding => write!(f, "Unsupported transfer coding"),
+        }
+    }
+}
+
+impl Error for HttpParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            HttpParseError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Why a handler could not decode the request body into the type it wanted.
+#[derive(Debug)]
+pub enum BodyError {
+    /// The body could not be read off the connection.
+    Read(HttpParseError),
+    /// The `Content-Type` is not the one the decoder handles.
+    UnsupportedMediaType,
+    /// The body does not match its `Content-Type` or the target type.
+    Malformed(String),
+    /// The body is not valid JSON for the target type. Carries the line and
+    /// column of the problem.
+    InvalidJson(serde_json::Error),
+    /// The body, or a part of it, is over the handler's size limit.
+    TooLarge,
+    /// Storing the body, e.g. an uploaded file, failed on our side.
+    Storage(io::Error),
+}
+
+impl BodyError {
+    /// Status code to answer the client with, or `None` when the connection
+    /// is unusable and should just be dropped.
+    pub fn status_code(&self) -> Option<StatusCode> {
+        match self {
+            BodyError::Read(e) => e.status_code(),
+            BodyError::UnsupportedMediaType => Some(StatusCode::UNSUPPORTED_MEDIA_TYPE),
+            BodyError::Malformed(_) | BodyError::InvalidJson(_) => Some(StatusCode::BAD_REQUEST),
+            BodyError::TooLarge => Some(StatusCode::CONTENT_TOO_LARGE),
+            BodyError::Storage(_) => Some(StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+}
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BodyError::Read(e) => write!(f, "Failed to read body: {}", e),
+            BodyError::UnsupportedMediaType => write!(f, "Unsupported media type"),
+            BodyError::Malformed(msg) => write!(f, "Malformed body: {}", msg),
+            BodyError::InvalidJson(e) => write!(f, "Invalid JSON: {}", e),
+            BodyError::TooLarge => write!(f, "Body too large"),
+            BodyError::Storage(e) => write!(f, "Failed to store body: {}", e),
+        }
+    }
+}
+
+impl Error for BodyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BodyError::Read(e) => Some(e),
+            BodyError::InvalidJson(e) => Some(e),
+            BodyError::Storage(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<HttpParseError> for BodyError {
+    fn from(e: HttpParseError) -> Self {
+        BodyError::Read(e)
+    }
+}
+
+impl From<io::Error> for HttpParseError {
+    fn from(e: io::Error) -> Self {
+        // Unwraps errors that were tunnelled through an `AsyncRead` body.
+        match e.downcast::<HttpParseError>() {
+            Ok(inner) => inner,
+            Err(e) => HttpParseError::Io(e),
+        }
+    }
+}
+
+impl From<HttpParseError> for io::Error {
+    fn from(e: HttpParseError) -> Self {
+        match e {
+            HttpParseError::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
@@ -1,6 +1,107 @@
-use std::collections::HashMap;
+use std::{
+    hash::{Hash, Hasher},
+    mem,
+    str::FromStr,
+};
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+use log::error;
+
+use super::{
+    accept::QualityList, auth::Authorization, cache_control::CacheControl, cookie::CookieJar,
+    date::HttpDate, error::HttpParseError, range::ByteRanges, target::parse_authority,
+};
+
+/// Whether `s` is a non-empty RFC 9110 token, the grammar of header names.
+pub fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/// Whether `value` can be written after a header name without ending the
+/// line early. CR, LF and NUL could inject headers or split the response.
+pub fn is_valid_value(value: &str) -> bool {
+    !value.bytes().any(|b| b == b'\r' || b == b'\n' || b == 0)
+}
+
+/// Splits a comma-separated list header value, leaving commas inside quoted
+/// strings alone. Empty elements are dropped.
+pub fn split_list(value: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                items.push(value[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(value[start..].trim());
+
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+/// Parameters following the first `;` of a header value such as
+/// `form-data; name="file"; filename="a.txt"`. Names are lower-cased and
+/// quoted values unescaped.
+pub fn header_params(value: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut rest = value.split_once(';').map_or("", |(_, rest)| rest);
+
+    loop {
+        rest = rest.trim_start_matches([' ', '\t', ';']);
+        let Some((name, after)) = rest.split_once('=') else {
+            break;
+        };
+        let name = name.trim().to_ascii_lowercase();
+        let after = after.trim_start();
+
+        let value = if let Some(quoted) = after.strip_prefix('"') {
+            let mut value = String::new();
+            let mut end = quoted.len();
+            let mut escaped = false;
+            for (i, c) in quoted.char_indices() {
+                match c {
+                    _ if escaped => {
+                        value.push(c);
+                        escaped = false;
+                    }
+                    '\\' => escaped = true,
+                    '"' => {
+                        end = i + 1;
+                        break;
+                    }
+                    _ => value.push(c),
+                }
+            }
+            rest = &quoted[end..];
+            value
+        } else {
+            let end = after.find(';').unwrap_or(after.len());
+            rest = &after[end..];
+            after[..end].trim().to_string()
+        };
+
+        params.push((name, value));
+    }
+
+    params
+}
+
+/// Header field name. Known names have their own variant, anything else is
+/// kept in `Custom` with its original spelling. Names compare and hash
+/// case-insensitively, so build `Custom` values through `From<&str>` to
+/// have a known name end up in its variant.
+#[derive(Debug, Clone)]
 pub enum HttpHeaderName {
     CacheControl,
     Connection,
@@ -36,6 +137,7 @@ pub enum HttpHeaderName {
     UserAgent,
     Age,
     Allow,
+    ContentDisposition,
     ContentEncoding,
     ContentLanguage,
     ContentLocation,
@@ -47,6 +149,7 @@ pub enum HttpHeaderName {
     ProxyAuthenticate,
     RetryAfter,
     Server,
+    SetCookie,
     Vary,
     WWWAuthenticate,
     Custom(String),
@@ -89,6 +192,7 @@ impl From<&str> for HttpHeaderName {
             "user-agent" => HttpHeaderName::UserAgent,
             "age" => HttpHeaderName::Age,
             "allow" => HttpHeaderName::Allow,
+            "content-disposition" => HttpHeaderName::ContentDisposition,
             "content-encoding" => HttpHeaderName::ContentEncoding,
             "content-language" => HttpHeaderName::ContentLanguage,
             "content-location" => HttpHeaderName::ContentLocation,
@@ -100,6 +204,7 @@ impl From<&str> for HttpHeaderName {
             "proxy-authenticate" => HttpHeaderName::ProxyAuthenticate,
             "retry-after" => HttpHeaderName::RetryAfter,
             "server" => HttpHeaderName::Server,
+            "set-cookie" => HttpHeaderName::SetCookie,
             "vary" => HttpHeaderName::Vary,
             "www-authenticate" => HttpHeaderName::WWWAuthenticate,
             _ => HttpHeaderName::Custom(s.to_string()),
@@ -107,7 +212,48 @@ impl From<&str> for HttpHeaderName {
     }
 }
 
+impl FromStr for HttpHeaderName {
+    type Err = HttpParseError;
+
+    /// Like `From<&str>`, but rejects names that are not RFC 9110 tokens.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !is_token(s) {
+            return Err(HttpParseError::BadHeader);
+        }
+
+        Ok(HttpHeaderName::from(s))
+    }
+}
+
+impl PartialEq for HttpHeaderName {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (HttpHeaderName::Custom(a), HttpHeaderName::Custom(b)) => a.eq_ignore_ascii_case(b),
+            _ => mem::discriminant(self) == mem::discriminant(other),
+        }
+    }
+}
+
+impl Eq for HttpHeaderName {}
+
+impl Hash for HttpHeaderName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        if let HttpHeaderName::Custom(s) = self {
+            for b in s.bytes() {
+                state.write_u8(b.to_ascii_lowercase());
+            }
+        }
+    }
+}
+
 impl HttpHeaderName {
+    /// Whether the name can be written out, i.e. is a token. Only `Custom`
+    /// names can fail this.
+    pub fn is_valid(&self) -> bool {
+        is_token(self.as_str())
+    }
+
     pub fn as_str(&self) -> &str {
         match self {
             HttpHeaderName::CacheControl => "Cache-Control",
@@ -144,6 +290,7 @@ impl HttpHeaderName {
             HttpHeaderName::UserAgent => "User-Agent",
             HttpHeaderName::Age => "Age",
             HttpHeaderName::Allow => "Allow",
+            HttpHeaderName::ContentDisposition => "Content-Disposition",
             HttpHeaderName::ContentEncoding => "Content-Encoding",
             HttpHeaderName::ContentLanguage => "Content-Language",
             HttpHeaderName::ContentLocation => "Content-Location",
@@ -155,6 +302,7 @@ impl HttpHeaderName {
             HttpHeaderName::ProxyAuthenticate => "Proxy-Authenticate",
             HttpHeaderName::RetryAfter => "Retry-After",
             HttpHeaderName::Server => "Server",
+            HttpHeaderName::SetCookie => "Set-Cookie",
             HttpHeaderName::Vary => "Vary",
             HttpHeaderName::WWWAuthenticate => "WWW-Authenticate",
             HttpHeaderName::Custom(s) => s.as_str(),
@@ -168,6 +316,12 @@ pub enum HttpHeaderValue {
     ContentType(String),
     Host(String, Option<u16>),
     Connection(ConnectionHeaderValue),
+    Date(HttpDate),
+    CacheControl(CacheControl),
+    Accept(QualityList),
+    Range(ByteRanges),
+    Authorization(Authorization),
+    /// Any other header, or a typed one whose value failed to parse.
     Raw(String),
 }
 
@@ -186,34 +340,55 @@ impl HttpHeaderValue {
                 HttpHeaderValue::ContentLength(value.parse::<usize>().unwrap_or(0))
             }
             HttpHeaderName::ContentType => HttpHeaderValue::ContentType(value.to_string()),
-            HttpHeaderName::Host => {
-                let parts: Vec<&str> = value.split(':').collect();
-                let host = parts[0].to_string();
-                let port = if parts.len() > 1 {
-                    parts[1].parse::<u16>().ok()
-                } else {
-                    None
-                };
-                HttpHeaderValue::Host(host, port)
-            }
+            HttpHeaderName::Host => match parse_authority(value) {
+                Some((host, port)) => HttpHeaderValue::Host(host, port),
+                None => HttpHeaderValue::Raw(value.to_string()),
+            },
             HttpHeaderName::Connection => match value.to_ascii_lowercase().as_str() {
                 "close" => HttpHeaderValue::Connection(ConnectionHeaderValue::Close),
                 "keep-alive" => HttpHeaderValue::Connection(ConnectionHeaderValue::KeepAlive),
                 "upgrade" => HttpHeaderValue::Connection(ConnectionHeaderValue::Upgrade),
                 _ => HttpHeaderValue::Connection(ConnectionHeaderValue::Custom(value.to_string())),
             },
+            HttpHeaderName::Date
+            | HttpHeaderName::Expires
+            | HttpHeaderName::LastModified
+            | HttpHeaderName::IfModifiedSince
+            | HttpHeaderName::IfUnmodifiedSince => Self::typed(value, HttpHeaderValue::Date),
+            HttpHeaderName::CacheControl => Self::typed(value, HttpHeaderValue::CacheControl),
+            HttpHeaderName::Accept
+            | HttpHeaderName::AcceptCharset
+            | HttpHeaderName::AcceptEncoding
+            | HttpHeaderName::AcceptLanguage => Self::typed(value, HttpHeaderValue::Accept),
+            HttpHeaderName::Range => Self::typed(value, HttpHeaderValue::Range),
+            HttpHeaderName::Authorization | HttpHeaderName::ProxyAuthorization => {
+                Self::typed(value, HttpHeaderValue::Authorization)
+            }
             _ => HttpHeaderValue::Raw(value.to_string()),
         }
     }
 
+    /// Parses `value` into a typed variant, keeping it raw if that fails.
+    fn typed<T: FromStr>(value: &str, variant: fn(T) -> Self) -> Self {
+        value
+            .parse()
+            .map_or_else(|_| HttpHeaderValue::Raw(value.to_string()), variant)
+    }
+
     pub fn as_str(&self) -> String {
         match self {
             HttpHeaderValue::ContentLength(cl) => cl.to_string(),
             HttpHeaderValue::ContentType(ct) => ct.clone(),
-            HttpHeaderValue::Host(a, p) => format!("{}:{}", a, p.unwrap_or(80).to_string()),
+            HttpHeaderValue::Host(host, Some(port)) => format!("{}:{}", host, port),
+            HttpHeaderValue::Host(host, None) => host.clone(),
             HttpHeaderValue::Connection(connection_header_value) => {
                 connection_header_value.as_str()
             }
+            HttpHeaderValue::Date(date) => date.to_string(),
+            HttpHeaderValue::CacheControl(cc) => cc.to_string(),
+            HttpHeaderValue::Accept(list) => list.to_string(),
+            HttpHeaderValue::Range(ranges) => ranges.to_string(),
+            HttpHeaderValue::Authorization(auth) => auth.to_string(),
             HttpHeaderValue::Raw(s) => s.clone(),
         }
     }
@@ -230,83 +405,483 @@ impl ConnectionHeaderValue {
     }
 }
 
+/// Header fields in the order they were received or added. A name can
+/// appear any number of times, and every value is written out.
 #[derive(Debug, Default)]
 pub struct HttpHeaders {
-    pub values: HashMap<HttpHeaderName, Vec<HttpHeaderValue>>,
+    entries: Vec<(HttpHeaderName, HttpHeaderValue)>,
 }
 
 impl HttpHeaders {
     pub fn new() -> Self {
         HttpHeaders {
-            values: HashMap::new(),
+            entries: Vec::new(),
         }
     }
 
-    pub fn add(&mut self, name: HttpHeaderName, value_str: &str) {
+    /// Adds a value after any existing ones, for headers that may repeat.
+    /// Values with CR, LF or NUL are dropped.
+    pub fn append(&mut self, name: HttpHeaderName, value_str: &str) {
+        if !check_value(&name, value_str) {
+            return;
+        }
+
         let value = HttpHeaderValue::parse(&name, value_str);
-        self.values.entry(name).or_insert_with(Vec::new).push(value);
+        self.entries.push((name, value));
+    }
+
+    /// Replaces every value of `name` with `value_str`, keeping the position
+    /// of the first one. Values with CR, LF or NUL are dropped and leave the
+    /// header as it was.
+    pub fn insert(&mut self, name: HttpHeaderName, value_str: &str) {
+        if !check_value(&name, value_str) {
+            return;
+        }
+
+        let value = HttpHeaderValue::parse(&name, value_str);
+        self.insert_value(name, value);
+    }
+
+    /// Like `insert`, for a value that is already typed.
+    pub fn insert_value(&mut self, name: HttpHeaderName, value: HttpHeaderValue) {
+        match self.entries.iter().position(|(n, _)| *n == name) {
+            Some(i) => {
+                self.entries[i].1 = value;
+                let rest = self.entries.split_off(i + 1);
+                self.entries
+                    .extend(rest.into_iter().filter(|(n, _)| *n != name));
+            }
+            None => self.entries.push((name, value)),
+        }
+    }
+
+    /// Removes every value of `name`, returning them in order.
+    pub fn remove(&mut self, name: &HttpHeaderName) -> Vec<HttpHeaderValue> {
+        let (removed, kept) = std::mem::take(&mut self.entries)
+            .into_iter()
+            .partition(|(n, _)| n == name);
+        self.entries = kept;
+
+        removed.into_iter().map(|(_, value)| value).collect()
+    }
+
+    /// Appends every value of `other`, e.g. trailer fields of a chunked body.
+    pub fn extend(&mut self, other: HttpHeaders) {
+        self.entries.extend(other.entries);
+    }
+
+    /// First value of `name`.
+    pub fn get(&self, name: &HttpHeaderName) -> Option<&HttpHeaderValue> {
+        self.entries
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value)
     }
 
-    pub fn get(&self, name: &HttpHeaderName) -> Option<&Vec<HttpHeaderValue>> {
-        self.values.get(name)
+    /// Every value of `name`, in order.
+    pub fn get_all<'a>(
+        &'a self,
+        name: &'a HttpHeaderName,
+    ) -> impl Iterator<Item = &'a HttpHeaderValue> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(n, _)| n == name)
+            .map(|(_, value)| value)
+    }
+
+    pub fn contains(&self, name: &HttpHeaderName) -> bool {
+        self.entries.iter().any(|(n, _)| n == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&HttpHeaderName, &HttpHeaderValue)> {
+        self.entries.iter().map(|(name, value)| (name, value))
+    }
+
+    /// Number of header lines, counting repeated names once per value.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
     }
 
     pub fn get_one_raw(&self, name: &HttpHeaderName) -> Option<&str> {
-        self.get(name)
-            .and_then(|values| values.first())
-            .and_then(|value| match value {
-                HttpHeaderValue::Raw(s) => Some(s.as_str()),
-                _ => None,
-            })
+        self.get(name).and_then(|value| match value {
+            HttpHeaderValue::Raw(s) => Some(s.as_str()),
+            _ => None,
+        })
     }
 
     pub fn content_length(&self) -> Option<usize> {
-        self.get(&HttpHeaderName::ContentLength)
-            .and_then(|values| values.first())
-            .and_then(|value| {
-                if let HttpHeaderValue::ContentLength(len) = value {
-                    Some(*len)
-                } else {
-                    None
-                }
+        self.get(&HttpHeaderName::ContentLength).and_then(|value| {
+            if let HttpHeaderValue::ContentLength(len) = value {
+                Some(*len)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Transfer codings of all `Transfer-Encoding` headers, lower-cased and
+    /// in the order they were applied.
+    pub fn transfer_encoding(&self) -> Vec<String> {
+        self.get_all(&HttpHeaderName::TransferEncoding)
+            .flat_map(|value| {
+                value
+                    .as_str()
+                    .split(',')
+                    .map(|coding| coding.trim().to_ascii_lowercase())
+                    .filter(|coding| !coding.is_empty())
+                    .collect::<Vec<String>>()
             })
+            .collect()
+    }
+
+    /// True when `chunked` is the final transfer coding applied to the body.
+    pub fn is_chunked(&self) -> bool {
+        self.transfer_encoding()
+            .last()
+            .is_some_and(|coding| coding == "chunked")
+    }
+
+    /// Whether any `Connection` header lists `token`, e.g. `close`.
+    pub fn connection_has(&self, token: &str) -> bool {
+        self.get_all(&HttpHeaderName::Connection).any(|value| {
+            value
+                .as_str()
+                .split(',')
+                .any(|t| t.trim().eq_ignore_ascii_case(token))
+        })
     }
 
     pub fn content_type(&self) -> Option<&str> {
-        self.get(&HttpHeaderName::ContentType)
-            .and_then(|values| values.first())
-            .and_then(|value| {
-                if let HttpHeaderValue::ContentType(t) = value {
-                    Some(t.as_str())
-                } else {
-                    None
-                }
-            })
+        self.get(&HttpHeaderName::ContentType).and_then(|value| {
+            if let HttpHeaderValue::ContentType(t) = value {
+                Some(t.as_str())
+            } else {
+                None
+            }
+        })
     }
 
+    /// Whether the `Content-Type` media type, ignoring its parameters, is
+    /// `media_type`.
+    pub fn has_media_type(&self, media_type: &str) -> bool {
+        self.content_type().is_some_and(|value| {
+            value
+                .split(';')
+                .next()
+                .unwrap_or("")
+                .trim()
+                .eq_ignore_ascii_case(media_type)
+        })
+    }
+
+    /// Every header line in order, joined with CRLF. Lines with a name that
+    /// is not a token or a value with CR, LF or NUL are left out, as they
+    /// could split the header.
     pub fn as_str(&self) -> String {
-        let header_string = self
-            .values
+        self.entries
             .iter()
-            .map(|(key, value)| {
-                let header_value = value.first().map_or("".to_string(), |v| v.as_str());
-                format!("{}: {}", key.as_str(), header_value)
-            })
+            .filter(|(name, _)| name.is_valid())
+            .map(|(name, value)| (name, value.as_str()))
+            .filter(|(_, value)| is_valid_value(value))
+            .map(|(name, value)| format!("{}: {}", name.as_str(), value))
             .collect::<Vec<String>>()
-            .join("\r\n");
+            .join("\r\n")
+    }
+
+    /// Every cookie of every `Cookie` header.
+    pub fn cookies(&self) -> CookieJar {
+        let mut jar = CookieJar::new();
+
+        for value in self.get_all(&HttpHeaderName::Cookie) {
+            jar.parse_header(&value.as_str());
+        }
+
+        jar
+    }
+
+    /// Value of an HTTP-date header such as `If-Modified-Since`.
+    pub fn date_value(&self, name: &HttpHeaderName) -> Option<HttpDate> {
+        self.get(name).and_then(|value| {
+            if let HttpHeaderValue::Date(date) = value {
+                Some(*date)
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn date(&self) -> Option<HttpDate> {
+        self.date_value(&HttpHeaderName::Date)
+    }
+
+    pub fn last_modified(&self) -> Option<HttpDate> {
+        self.date_value(&HttpHeaderName::LastModified)
+    }
+
+    pub fn if_modified_since(&self) -> Option<HttpDate> {
+        self.date_value(&HttpHeaderName::IfModifiedSince)
+    }
+
+    pub fn if_unmodified_since(&self) -> Option<HttpDate> {
+        self.date_value(&HttpHeaderName::IfUnmodifiedSince)
+    }
+
+    /// Directives of all `Cache-Control` headers.
+    pub fn cache_control(&self) -> Option<CacheControl> {
+        let mut merged: Option<CacheControl> = None;
 
-        header_string
+        for value in self.get_all(&HttpHeaderName::CacheControl) {
+            if let HttpHeaderValue::CacheControl(cc) = value {
+                merged.get_or_insert_default().extend(cc.clone());
+            }
+        }
+
+        merged
+    }
+
+    /// Elements of all headers called `name`, which should be one of the
+    /// `Accept` family.
+    pub fn quality_list(&self, name: &HttpHeaderName) -> Option<QualityList> {
+        let mut merged: Option<QualityList> = None;
+
+        for value in self.get_all(name) {
+            if let HttpHeaderValue::Accept(list) = value {
+                merged.get_or_insert_default().extend(list.clone());
+            }
+        }
+
+        merged
+    }
+
+    pub fn accept(&self) -> Option<QualityList> {
+        self.quality_list(&HttpHeaderName::Accept)
+    }
+
+    pub fn accept_encoding(&self) -> Option<QualityList> {
+        self.quality_list(&HttpHeaderName::AcceptEncoding)
+    }
+
+    pub fn accept_language(&self) -> Option<QualityList> {
+        self.quality_list(&HttpHeaderName::AcceptLanguage)
+    }
+
+    pub fn range(&self) -> Option<&ByteRanges> {
+        self.get(&HttpHeaderName::Range).and_then(|value| {
+            if let HttpHeaderValue::Range(ranges) = value {
+                Some(ranges)
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn authorization(&self) -> Option<&Authorization> {
+        self.get(&HttpHeaderName::Authorization).and_then(|value| {
+            if let HttpHeaderValue::Authorization(auth) = value {
+                Some(auth)
+            } else {
+                None
+            }
+        })
     }
 
+    /// Host and port of a valid `Host` header. IPv6 literals keep their
+    /// brackets, e.g. `[::1]`.
     pub fn host(&self) -> Option<(&str, Option<u16>)> {
-        self.get(&HttpHeaderName::Host)
-            .and_then(|values| values.first())
-            .and_then(|value| {
-                if let HttpHeaderValue::Host(host, port) = value {
-                    Some((host.as_str(), *port))
-                } else {
-                    None
-                }
-            })
+        self.get(&HttpHeaderName::Host).and_then(|value| {
+            if let HttpHeaderValue::Host(host, port) = value {
+                Some((host.as_str(), *port))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+fn check_value(name: &HttpHeaderName, value: &str) -> bool {
+    let valid = is_valid_value(value);
+    if !valid {
+        error!(
+            "Dropping {} header with CR, LF or NUL in its value",
+            name.as_str()
+        );
+    }
+
+    valid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_insertion_order_and_repeats() {
+        let mut headers = HttpHeaders::new();
+        headers.append(HttpHeaderName::Vary, "Accept");
+        headers.append(HttpHeaderName::ContentType, "text/plain");
+        headers.append(HttpHeaderName::Vary, "Cookie");
+
+        assert_eq!(
+            headers.as_str(),
+            "Vary: Accept\r\nContent-Type: text/plain\r\nVary: Cookie"
+        );
+        assert_eq!(headers.get_all(&HttpHeaderName::Vary).count(), 2);
+    }
+
+    #[test]
+    fn insert_replaces_in_place() {
+        let mut headers = HttpHeaders::new();
+        headers.append(HttpHeaderName::Connection, "keep-alive");
+        headers.append(HttpHeaderName::ContentLength, "1");
+        headers.append(HttpHeaderName::Connection, "upgrade");
+
+        headers.insert(HttpHeaderName::Connection, "close");
+        headers.insert(HttpHeaderName::Server, "test");
+
+        assert_eq!(
+            headers.as_str(),
+            "Connection: close\r\nContent-Length: 1\r\nServer: test"
+        );
+    }
+
+    #[test]
+    fn removes_every_value() {
+        let mut headers = HttpHeaders::new();
+        headers.append(HttpHeaderName::SetCookie, "a=1");
+        headers.append(HttpHeaderName::ContentLength, "1");
+        headers.append(HttpHeaderName::SetCookie, "b=2");
+
+        let removed = headers.remove(&HttpHeaderName::SetCookie);
+
+        assert_eq!(removed.len(), 2);
+        assert_eq!(headers.len(), 1);
+        assert!(!headers.contains(&HttpHeaderName::SetCookie));
+    }
+
+    #[test]
+    fn matches_custom_names_case_insensitively() {
+        let mut headers = HttpHeaders::new();
+        headers.append(HttpHeaderName::from("x-request-id"), "1");
+        headers.insert(HttpHeaderName::from("X-REQUEST-ID"), "2");
+
+        assert_eq!(
+            headers.get_one_raw(&HttpHeaderName::from("X-Request-Id")),
+            Some("2")
+        );
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers.as_str(), "x-request-id: 2");
+
+        let set: std::collections::HashSet<_> =
+            [HttpHeaderName::from("X-Foo"), HttpHeaderName::from("x-foo")]
+                .into_iter()
+                .collect();
+        assert_eq!(set.len(), 1);
+        assert_ne!(HttpHeaderName::from("X-Foo"), HttpHeaderName::from("X-Bar"));
+        assert_eq!(HttpHeaderName::from("HOST"), HttpHeaderName::Host);
+    }
+
+    #[test]
+    fn validates_names_as_tokens() {
+        assert!("X-Request-Id".parse::<HttpHeaderName>().is_ok());
+        assert!("Bad Name".parse::<HttpHeaderName>().is_err());
+        assert!("".parse::<HttpHeaderName>().is_err());
+
+        let mut headers = HttpHeaders::new();
+        headers.append(HttpHeaderName::from("X-Evil\r\nInjected"), "1");
+        headers.append(HttpHeaderName::from("X-Good"), "2");
+        assert_eq!(headers.as_str(), "X-Good: 2");
+    }
+
+    #[test]
+    fn drops_values_that_could_split_the_header() {
+        let mut headers = HttpHeaders::new();
+        headers.append(HttpHeaderName::Location, "/ok");
+        headers.insert(HttpHeaderName::Location, "/a\r\nSet-Cookie: x=1");
+        headers.append(HttpHeaderName::from("X-Nul"), "a\0b");
+        headers.insert_value(
+            HttpHeaderName::from("X-Raw"),
+            HttpHeaderValue::Raw("a\nb".to_string()),
+        );
+
+        assert_eq!(headers.as_str(), "Location: /ok");
+    }
+
+    #[test]
+    fn exposes_typed_values() {
+        let mut headers = HttpHeaders::new();
+        headers.append(
+            HttpHeaderName::IfModifiedSince,
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+        );
+        headers.append(HttpHeaderName::CacheControl, "no-cache");
+        headers.append(HttpHeaderName::CacheControl, "max-age=5");
+        headers.append(HttpHeaderName::AcceptEncoding, "gzip;q=0.5, br");
+        headers.append(HttpHeaderName::Range, "bytes=0-9");
+        headers.append(HttpHeaderName::Authorization, "Bearer abc");
+        headers.append(HttpHeaderName::Date, "yesterday");
+
+        assert_eq!(
+            headers.if_modified_since(),
+            Some(HttpDate::from_unix_secs(784111777))
+        );
+        let cc = headers.cache_control().unwrap();
+        assert!(cc.no_cache());
+        assert_eq!(cc.max_age(), Some(std::time::Duration::from_secs(5)));
+        assert_eq!(
+            headers
+                .accept_encoding()
+                .unwrap()
+                .negotiate(&["gzip", "br"]),
+            Some("br")
+        );
+        assert_eq!(headers.range().unwrap().resolve(100), Some(vec![(0, 9)]));
+        assert_eq!(
+            headers.authorization(),
+            Some(&Authorization::Bearer("abc".to_string()))
+        );
+
+        // Unparsable values stay raw and are written back unchanged.
+        assert_eq!(headers.date(), None);
+        assert_eq!(
+            headers.get_one_raw(&HttpHeaderName::Date),
+            Some("yesterday")
+        );
+        assert!(
+            headers
+                .as_str()
+                .contains("If-Modified-Since: Sun, 06 Nov 1994 08:49:37 GMT")
+        );
+    }
+
+    #[test]
+    fn round_trips_host() {
+        for value in ["example.com", "example.com:8080", "[::1]:7878", "[::1]"] {
+            let parsed = HttpHeaderValue::parse(&HttpHeaderName::Host, value);
+
+            assert!(matches!(parsed, HttpHeaderValue::Host(..)), "{}", value);
+            assert_eq!(parsed.as_str(), value);
+        }
+
+        let mut headers = HttpHeaders::new();
+        headers.append(HttpHeaderName::Host, "[::1]:7878");
+        assert_eq!(headers.host(), Some(("[::1]", Some(7878))));
+    }
+
+    #[test]
+    fn parses_header_params() {
+        let params = header_params(r#"form-data; name="a;b"; filename="x\"y.txt"; size=3"#);
+
+        assert_eq!(
+            params,
+            vec![
+                ("name".to_string(), "a;b".to_string()),
+                ("filename".to_string(), "x\"y.txt".to_string()),
+                ("size".to_string(), "3".to_string()),
+            ]
+        );
     }
 }
@@ -0,0 +1,45 @@
+/// Upper bounds applied while reading a request, so a single client cannot
+/// make the server buffer arbitrary amounts of data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Longest accepted request line, including the trailing CRLF.
+    pub max_request_line: usize,
+    /// Total size of all header lines, including the trailing CRLFs.
+    pub max_header_bytes: usize,
+    pub max_header_count: usize,
+    pub max_body_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_request_line: 8 * 1024,
+            max_header_bytes: 64 * 1024,
+            max_header_count: 100,
+            max_body_size: 10 * 1024 * 1024,
+        }
+    }
+}
+
+/// Upper bounds applied while storing a `multipart/form-data` upload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadLimits {
+    /// Largest single file written to disk.
+    pub max_file_size: usize,
+    /// Largest non-file field, which is kept in memory.
+    pub max_field_size: usize,
+    /// Combined size of every file and field in the upload.
+    pub max_total_size: usize,
+    pub max_parts: usize,
+}
+
+impl Default for UploadLimits {
+    fn default() -> Self {
+        UploadLimits {
+            max_file_size: 10 * 1024 * 1024,
+            max_field_size: 64 * 1024,
+            max_total_size: 50 * 1024 * 1024,
+            max_parts: 100,
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    future::poll_fn,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use tokio::io::{self, AsyncBufRead};
+
+use super::error::HttpParseError;
+
+/// Appends bytes up to and including the next `\n` to `line`. Resolves to
+/// `Some(len)` with the total line length (0 at EOF), or `None` once the line
+/// would grow past `limit`. Safe to call again after `Pending`, as the partial
+/// line is kept in `line`.
+pub fn poll_read_line<R: AsyncBufRead + Unpin + ?Sized>(
+    cx: &mut Context<'_>,
+    reader: &mut R,
+    line: &mut Vec<u8>,
+    limit: usize,
+) -> Poll<io::Result<Option<usize>>> {
+    loop {
+        let available = ready!(Pin::new(&mut *reader).poll_fill_buf(cx))?;
+        if available.is_empty() {
+            return Poll::Ready(Ok(Some(line.len())));
+        }
+
+        let (done, used) = match available.iter().position(|&b| b == b'\n') {
+            Some(i) => (true, i + 1),
+            None => (false, available.len()),
+        };
+
+        if line.len() + used > limit {
+            return Poll::Ready(Ok(None));
+        }
+
+        line.extend_from_slice(&available[..used]);
+        Pin::new(&mut *reader).consume(used);
+
+        if done {
+            return Poll::Ready(Ok(Some(line.len())));
+        }
+    }
+}
+
+/// Like `read_line`, but gives up with `overflow` once the line grows past
+/// `limit` bytes instead of buffering it all. Invalid UTF-8 is replaced
+/// rather than treated as a transport error.
+pub async fn read_line_limited<R: AsyncBufRead + Unpin + ?Sized>(
+    reader: &mut R,
+    line: &mut String,
+    limit: usize,
+    overflow: HttpParseError,
+) -> Result<usize, HttpParseError> {
+    let mut bytes = Vec::new();
+
+    match poll_fn(|cx| poll_read_line(cx, reader, &mut bytes, limit)).await? {
+        Some(len) => {
+            line.push_str(&String::from_utf8_lossy(&bytes));
+            Ok(len)
+        }
+        None => Err(overflow),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reads_single_line() {
+        let mut reader: &[u8] = b"GET / HTTP/1.1\r\nHost: a\r\n";
+        let mut line = String::new();
+
+        let n = read_line_limited(&mut reader, &mut line, 64, HttpParseError::UriTooLong)
+            .await
+            .unwrap();
+
+        assert_eq!(n, 16);
+        assert_eq!(line, "GET / HTTP/1.1\r\n");
+        assert_eq!(reader, b"Host: a\r\n");
+    }
+
+    #[tokio::test]
+    async fn rejects_line_over_limit() {
+        let mut reader: &[u8] = b"GET /very/long/path HTTP/1.1\r\n";
+        let mut line = String::new();
+
+        let res = read_line_limited(&mut reader, &mut line, 16, HttpParseError::UriTooLong).await;
+
+        assert!(matches!(res, Err(HttpParseError::UriTooLong)));
+    }
+
+    #[tokio::test]
+    async fn returns_zero_at_eof() {
+        let mut reader: &[u8] = b"";
+        let mut line = String::new();
+
+        let n = read_line_limited(&mut reader, &mut line, 16, HttpParseError::UriTooLong)
+            .await
+            .unwrap();
+
+        assert_eq!(n, 0);
+    }
+}
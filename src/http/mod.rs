@@ -1,4 +1,21 @@
+pub mod accept;
+pub mod auth;
+pub mod body;
+pub mod cache_control;
+pub mod chunked;
+pub mod compression;
+pub mod cookie;
+pub mod date;
+pub mod error;
 pub mod headers;
+pub mod limits;
+pub mod line;
 pub mod method;
+pub mod multipart;
+pub mod range;
 pub mod request;
 pub mod response;
+pub mod status;
+pub mod target;
+pub mod url;
+pub mod version;
@@ -0,0 +1,534 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bytes::{Bytes, BytesMut};
+use tokio::{
+    fs::{self, File},
+    io::{AsyncReadExt, AsyncWriteExt},
+};
+
+use super::{
+    body::RequestBody,
+    error::{BodyError, HttpParseError},
+    headers::{HttpHeaderName, HttpHeaders, header_params},
+    limits::UploadLimits,
+    request::parse_header_line,
+    url::Params,
+};
+
+/// Largest header block accepted for a single part.
+const MAX_PART_HEADER: usize = 8 * 1024;
+
+/// How much is read from the request body at a time.
+const READ_SIZE: usize = 16 * 1024;
+
+/// Makes temp file names unique within the process.
+static UPLOAD_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Preamble,
+    Delimiter,
+    Headers,
+    Data,
+    Done,
+}
+
+/// Streaming `multipart/form-data` reader over a request body. Parts are
+/// handed out one at a time and only the bytes around the next boundary are
+/// kept in memory.
+pub struct Multipart<'a> {
+    body: &'a mut RequestBody,
+    /// `\r\n--boundary`, which ends every part.
+    delimiter: Vec<u8>,
+    buf: BytesMut,
+    state: State,
+}
+
+impl<'a> Multipart<'a> {
+    pub fn new(body: &'a mut RequestBody, boundary: &str) -> Self {
+        // The first delimiter may open the body without a leading CRLF.
+        let mut buf = BytesMut::with_capacity(READ_SIZE);
+        buf.extend_from_slice(b"\r\n");
+
+        Multipart {
+            body,
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            buf,
+            state: State::Preamble,
+        }
+    }
+
+    /// Next part, or `None` after the closing boundary. Whatever is left of
+    /// the previous part is skipped.
+    pub async fn next_part(&mut self) -> Result<Option<Part<'_, 'a>>, BodyError> {
+        loop {
+            match self.state {
+                State::Done => return Ok(None),
+                State::Preamble | State::Data => while self.read_data().await?.is_some() {},
+                State::Delimiter => self.read_delimiter().await?,
+                State::Headers => {
+                    let headers = self.read_headers().await?;
+                    self.state = State::Data;
+                    return Ok(Some(Part::new(self, headers)));
+                }
+            }
+        }
+    }
+
+    /// Stores every file part under `dir` and collects the other fields. On
+    /// error, the files written so far are removed again.
+    pub async fn save_uploads(
+        mut self,
+        dir: &Path,
+        limits: &UploadLimits,
+    ) -> Result<Upload, BodyError> {
+        let mut upload = Upload::default();
+
+        let res = self.save_parts(dir, limits, &mut upload).await;
+        if res.is_err() {
+            for file in &upload.files {
+                let _ = fs::remove_file(&file.path).await;
+            }
+        }
+
+        res.map(|_| upload)
+    }
+
+    async fn save_parts(
+        &mut self,
+        dir: &Path,
+        limits: &UploadLimits,
+        upload: &mut Upload,
+    ) -> Result<(), BodyError> {
+        let mut total = 0;
+        let mut parts = 0;
+
+        while let Some(mut part) = self.next_part().await? {
+            parts += 1;
+            if parts > limits.max_parts {
+                return Err(BodyError::TooLarge);
+            }
+
+            let remaining = limits.max_total_size - total;
+            let field = part.name().unwrap_or_default().to_string();
+
+            match part.filename().map(str::to_string) {
+                Some(filename) => {
+                    let max = limits.max_file_size.min(remaining);
+                    let (path, size) = part.save_to(dir, max).await?;
+                    total += size;
+
+                    upload.files.push(UploadedFile {
+                        field,
+                        filename,
+                        content_type: part.content_type().map(str::to_string),
+                        path,
+                        size,
+                    });
+                }
+                None => {
+                    let value = part.text(limits.max_field_size.min(remaining)).await?;
+                    total += value.len();
+                    upload.fields.add(&field, &value);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads more of the body into the buffer. False at the end of the body.
+    async fn fill(&mut self) -> Result<bool, BodyError> {
+        self.buf.reserve(READ_SIZE);
+        let n = self
+            .body
+            .read_buf(&mut self.buf)
+            .await
+            .map_err(HttpParseError::from)?;
+
+        Ok(n > 0)
+    }
+
+    /// Part data up to the next delimiter, or `None` once it is reached.
+    async fn read_data(&mut self) -> Result<Option<Bytes>, BodyError> {
+        loop {
+            if !matches!(self.state, State::Preamble | State::Data) {
+                return Ok(None);
+            }
+
+            if let Some(pos) = find(&self.buf, &self.delimiter) {
+                let data = self.buf.split_to(pos).freeze();
+                let preamble = self.state == State::Preamble;
+                self.state = State::Delimiter;
+
+                if data.is_empty() || preamble {
+                    return Ok(None);
+                }
+                return Ok(Some(data));
+            }
+
+            // Everything that cannot be the start of a delimiter is data.
+            let safe = self.buf.len().saturating_sub(self.delimiter.len() - 1);
+            if safe > 0 && self.state == State::Data {
+                return Ok(Some(self.buf.split_to(safe).freeze()));
+            }
+            let _ = self.buf.split_to(safe);
+
+            if !self.fill().await? {
+                return Err(malformed("missing closing boundary"));
+            }
+        }
+    }
+
+    /// Consumes a delimiter and the rest of its line, which tells whether
+    /// another part follows.
+    async fn read_delimiter(&mut self) -> Result<(), BodyError> {
+        let len = self.delimiter.len();
+
+        loop {
+            let rest = &self.buf[len..];
+            if rest.starts_with(b"--") {
+                self.buf.clear();
+                self.state = State::Done;
+                return Ok(());
+            }
+
+            if let Some(pos) = find(rest, b"\r\n") {
+                // Only transport padding may follow the boundary.
+                if !rest[..pos].iter().all(|b| *b == b' ' || *b == b'\t') {
+                    return Err(malformed("invalid boundary line"));
+                }
+                let _ = self.buf.split_to(len + pos + 2);
+                self.state = State::Headers;
+                return Ok(());
+            }
+
+            if rest.len() > 256 {
+                return Err(malformed("invalid boundary line"));
+            }
+            if !self.fill().await? {
+                return Err(malformed("missing closing boundary"));
+            }
+        }
+    }
+
+    async fn read_headers(&mut self) -> Result<HttpHeaders, BodyError> {
+        let mut headers = HttpHeaders::new();
+        let mut header_bytes = 0;
+
+        loop {
+            let Some(pos) = find(&self.buf, b"\r\n") else {
+                if header_bytes + self.buf.len() > MAX_PART_HEADER {
+                    return Err(BodyError::TooLarge);
+                }
+                if !self.fill().await? {
+                    return Err(malformed("truncated part headers"));
+                }
+                continue;
+            };
+
+            let line = self.buf.split_to(pos + 2);
+            if line.len() == 2 {
+                return Ok(headers);
+            }
+
+            header_bytes += line.len();
+            if header_bytes > MAX_PART_HEADER {
+                return Err(BodyError::TooLarge);
+            }
+
+            let line = String::from_utf8_lossy(&line);
+            let (name, value) =
+                parse_header_line(&line).ok_or_else(|| malformed("malformed part header"))?;
+            headers.append(name, value);
+        }
+    }
+}
+
+/// One part of a multipart body. Its data is read with `chunk`, or all at
+/// once with `bytes`, `text` or `save_to`.
+pub struct Part<'m, 'a> {
+    multipart: &'m mut Multipart<'a>,
+    headers: HttpHeaders,
+    name: Option<String>,
+    filename: Option<String>,
+}
+
+impl<'m, 'a> Part<'m, 'a> {
+    fn new(multipart: &'m mut Multipart<'a>, headers: HttpHeaders) -> Self {
+        let mut name = None;
+        let mut filename = None;
+
+        let disposition = headers
+            .get(&HttpHeaderName::ContentDisposition)
+            .map(|value| value.as_str())
+            .unwrap_or_default();
+        for (key, value) in header_params(&disposition) {
+            match key.as_str() {
+                "name" => name = Some(value),
+                "filename" => filename = Some(value),
+                _ => {}
+            }
+        }
+
+        Part {
+            multipart,
+            headers,
+            name,
+            filename,
+        }
+    }
+
+    pub fn headers(&self) -> &HttpHeaders {
+        &self.headers
+    }
+
+    /// Form field name from `Content-Disposition`.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// File name the client suggested. Never use it as a path as is.
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers.content_type()
+    }
+
+    /// Next piece of the part's data, or `None` once it has been fully read.
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, BodyError> {
+        self.multipart.read_data().await
+    }
+
+    /// Reads the rest of the part into memory, failing once it grows past
+    /// `max` bytes.
+    pub async fn bytes(&mut self, max: usize) -> Result<Vec<u8>, BodyError> {
+        let mut data = Vec::new();
+
+        while let Some(chunk) = self.chunk().await? {
+            if chunk.len() > max - data.len() {
+                return Err(BodyError::TooLarge);
+            }
+            data.extend_from_slice(&chunk);
+        }
+
+        Ok(data)
+    }
+
+    pub async fn text(&mut self, max: usize) -> Result<String, BodyError> {
+        String::from_utf8(self.bytes(max).await?).map_err(|_| malformed("field is not UTF-8"))
+    }
+
+    /// Streams the rest of the part into a new file under `dir`, failing
+    /// once it grows past `max` bytes. Returns the file's path and size. The
+    /// file name is generated, so the client cannot pick where it lands.
+    pub async fn save_to(&mut self, dir: &Path, max: usize) -> Result<(PathBuf, usize), BodyError> {
+        let path = dir.join(temp_file_name());
+        let mut file = File::create_new(&path).await.map_err(BodyError::Storage)?;
+
+        let res = self.write_to_file(&mut file, max).await;
+        if res.is_err() {
+            drop(file);
+            let _ = fs::remove_file(&path).await;
+        }
+
+        res.map(|size| (path, size))
+    }
+
+    async fn write_to_file(&mut self, file: &mut File, max: usize) -> Result<usize, BodyError> {
+        let mut size = 0;
+
+        while let Some(chunk) = self.chunk().await? {
+            if chunk.len() > max - size {
+                return Err(BodyError::TooLarge);
+            }
+            size += chunk.len();
+            file.write_all(&chunk).await.map_err(BodyError::Storage)?;
+        }
+
+        file.flush().await.map_err(BodyError::Storage)?;
+
+        Ok(size)
+    }
+}
+
+/// Result of `Multipart::save_uploads`.
+#[derive(Debug, Default)]
+pub struct Upload {
+    pub fields: Params,
+    pub files: Vec<UploadedFile>,
+}
+
+/// A file part stored on disk. The file is left in place for the handler
+/// to move or delete.
+#[derive(Debug)]
+pub struct UploadedFile {
+    pub field: String,
+    pub filename: String,
+    pub content_type: Option<String>,
+    pub path: PathBuf,
+    pub size: usize,
+}
+
+fn temp_file_name() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    let n = UPLOAD_COUNTER.fetch_add(1, Ordering::Relaxed);
+
+    format!("upload-{}-{}-{}", std::process::id(), nanos, n)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn malformed(msg: &str) -> BodyError {
+    BodyError::Malformed(msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::BufReader;
+
+    use super::*;
+    use crate::http::{body::BodyFraming, limits::Limits};
+
+    const BODY: &[u8] = b"preamble\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\
+        \r\n\
+        Holiday\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"photo\"; filename=\"beach \\\"1\\\".jpg\"\r\n\
+        Content-Type: image/jpeg\r\n\
+        \r\n\
+        \r\n--XyNot-a-boundary\r\n\
+        --XyZ--\r\n\
+        epilogue";
+
+    /// Body whose reader hands out a few bytes at a time, so boundaries get
+    /// split across reads.
+    fn body(input: &'static [u8]) -> RequestBody {
+        let reader = BufReader::with_capacity(3, input);
+        RequestBody::streaming(
+            Box::new(reader),
+            BodyFraming::Length(input.len()),
+            &Limits::default(),
+        )
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("multipart-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn reads_parts_across_small_reads() {
+        let mut body = body(BODY);
+        let mut multipart = Multipart::new(&mut body, "XyZ");
+
+        let mut part = multipart.next_part().await.unwrap().unwrap();
+        assert_eq!(part.name(), Some("title"));
+        assert_eq!(part.filename(), None);
+        assert_eq!(part.text(1024).await.unwrap(), "Holiday");
+
+        let mut part = multipart.next_part().await.unwrap().unwrap();
+        assert_eq!(part.name(), Some("photo"));
+        assert_eq!(part.filename(), Some("beach \"1\".jpg"));
+        assert_eq!(part.content_type(), Some("image/jpeg"));
+        assert_eq!(part.bytes(1024).await.unwrap(), b"\r\n--XyNot-a-boundary");
+
+        assert!(multipart.next_part().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn skips_unread_parts() {
+        let mut body = body(BODY);
+        let mut multipart = Multipart::new(&mut body, "XyZ");
+
+        let mut names = Vec::new();
+        while let Some(part) = multipart.next_part().await.unwrap() {
+            names.push(part.name().unwrap().to_string());
+        }
+
+        assert_eq!(names, vec!["title", "photo"]);
+    }
+
+    #[tokio::test]
+    async fn saves_uploads_to_disk() {
+        let dir = temp_dir("save");
+        let mut body = body(BODY);
+
+        let upload = Multipart::new(&mut body, "XyZ")
+            .save_uploads(&dir, &UploadLimits::default())
+            .await
+            .unwrap();
+
+        assert_eq!(upload.fields.get("title"), Some("Holiday"));
+        assert_eq!(upload.files.len(), 1);
+
+        let file = &upload.files[0];
+        assert_eq!(file.field, "photo");
+        assert_eq!(file.size, 20);
+        assert!(file.path.starts_with(&dir));
+        assert_eq!(
+            std::fs::read(&file.path).unwrap(),
+            b"\r\n--XyNot-a-boundary"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn enforces_upload_limits() {
+        let dir = temp_dir("limits");
+
+        for limits in [
+            UploadLimits {
+                max_file_size: 19,
+                ..UploadLimits::default()
+            },
+            UploadLimits {
+                max_total_size: 25,
+                ..UploadLimits::default()
+            },
+            UploadLimits {
+                max_parts: 1,
+                ..UploadLimits::default()
+            },
+        ] {
+            let mut body = body(BODY);
+            let res = Multipart::new(&mut body, "XyZ")
+                .save_uploads(&dir, &limits)
+                .await;
+
+            assert!(matches!(res, Err(BodyError::TooLarge)), "{:?}", limits);
+        }
+
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_unterminated_body() {
+        let mut body = body(b"--XyZ\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nvalue");
+        let mut multipart = Multipart::new(&mut body, "XyZ");
+
+        let mut part = multipart.next_part().await.unwrap().unwrap();
+
+        assert!(matches!(
+            part.bytes(1024).await,
+            Err(BodyError::Malformed(_))
+        ));
+    }
+}
@@ -0,0 +1,161 @@
+use std::{fmt, str::FromStr};
+
+use super::error::HttpParseError;
+
+/// One range of a `Range: bytes=...` header. Bounds are inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// `first-last`
+    FromTo(u64, u64),
+    /// `first-`, up to the end.
+    From(u64),
+    /// `-len`, the last `len` bytes.
+    Suffix(u64),
+}
+
+impl ByteRange {
+    /// Inclusive `(first, last)` offsets within a representation of `len`
+    /// bytes, or `None` if the range is not satisfiable.
+    pub fn resolve(&self, len: u64) -> Option<(u64, u64)> {
+        match *self {
+            ByteRange::FromTo(first, _) | ByteRange::From(first) if first >= len => None,
+            ByteRange::FromTo(first, last) => Some((first, last.min(len - 1))),
+            ByteRange::From(first) => Some((first, len - 1)),
+            ByteRange::Suffix(0) => None,
+            ByteRange::Suffix(_) if len == 0 => None,
+            ByteRange::Suffix(suffix) => Some((len.saturating_sub(suffix), len - 1)),
+        }
+    }
+}
+
+impl fmt::Display for ByteRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ByteRange::FromTo(first, last) => write!(f, "{}-{}", first, last),
+            ByteRange::From(first) => write!(f, "{}-", first),
+            ByteRange::Suffix(len) => write!(f, "-{}", len),
+        }
+    }
+}
+
+/// A `Range: bytes=...` header. Other range units are rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ByteRanges {
+    pub ranges: Vec<ByteRange>,
+}
+
+impl ByteRanges {
+    /// The satisfiable ranges for a representation of `len` bytes, or `None`
+    /// when there are none and the answer should be 416.
+    pub fn resolve(&self, len: u64) -> Option<Vec<(u64, u64)>> {
+        let ranges: Vec<(u64, u64)> = self.ranges.iter().filter_map(|r| r.resolve(len)).collect();
+
+        (!ranges.is_empty()).then_some(ranges)
+    }
+}
+
+impl FromStr for ByteRanges {
+    type Err = HttpParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (unit, specs) = s.split_once('=').ok_or(HttpParseError::BadHeader)?;
+        if !unit.trim().eq_ignore_ascii_case("bytes") {
+            return Err(HttpParseError::BadHeader);
+        }
+
+        let ranges = specs
+            .split(',')
+            .map(str::trim)
+            .filter(|spec| !spec.is_empty())
+            .map(parse_range)
+            .collect::<Option<Vec<ByteRange>>>()
+            .ok_or(HttpParseError::BadHeader)?;
+        if ranges.is_empty() {
+            return Err(HttpParseError::BadHeader);
+        }
+
+        Ok(ByteRanges { ranges })
+    }
+}
+
+impl fmt::Display for ByteRanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bytes=")?;
+        for (i, range) in self.ranges.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", range)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_range(spec: &str) -> Option<ByteRange> {
+    let (first, last) = spec.split_once('-')?;
+
+    match (parse_offset(first), parse_offset(last)) {
+        (Some(first), Some(last)) if first <= last => Some(ByteRange::FromTo(first, last)),
+        (Some(first), None) if last.is_empty() => Some(ByteRange::From(first)),
+        (None, Some(len)) if first.is_empty() => Some(ByteRange::Suffix(len)),
+        _ => None,
+    }
+}
+
+fn parse_offset(s: &str) -> Option<u64> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    s.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_range_specs() {
+        let ranges = "bytes=0-499, 500-, -200".parse::<ByteRanges>().unwrap();
+
+        assert_eq!(
+            ranges.ranges,
+            vec![
+                ByteRange::FromTo(0, 499),
+                ByteRange::From(500),
+                ByteRange::Suffix(200),
+            ]
+        );
+        assert_eq!(ranges.to_string(), "bytes=0-499, 500-, -200");
+    }
+
+    #[test]
+    fn rejects_invalid_ranges() {
+        for s in [
+            "bytes=",
+            "bytes=5-1",
+            "bytes=-",
+            "bytes=a-b",
+            "bytes=+1-2",
+            "items=0-1",
+            "0-1",
+        ] {
+            assert!(s.parse::<ByteRanges>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn resolves_against_length() {
+        let ranges = "bytes=0-99, 950-2000, -50, 1000-"
+            .parse::<ByteRanges>()
+            .unwrap();
+
+        assert_eq!(
+            ranges.resolve(1000),
+            Some(vec![(0, 99), (950, 999), (950, 999)])
+        );
+        assert_eq!(ByteRange::Suffix(5000).resolve(10), Some((0, 9)));
+        assert_eq!("bytes=10-".parse::<ByteRanges>().unwrap().resolve(10), None);
+    }
+}
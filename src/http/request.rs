@@ -1,13 +1,21 @@
 use std::str::FromStr;
 
-use tokio::{
-    io::{self, AsyncBufReadExt, AsyncReadExt, BufReader},
-    net::tcp::OwnedReadHalf,
-};
+use serde::de::DeserializeOwned;
+use tokio::io::{self, AsyncBufRead, AsyncReadExt};
 
 use super::{
-    headers::{HttpHeaderName, HttpHeaders},
+    body::{BodyFraming, BoxedReader, RequestBody},
+    chunked::read_chunked_body,
+    cookie::CookieJar,
+    error::{BodyError, HttpParseError},
+    headers::{HttpHeaderName, HttpHeaderValue, HttpHeaders, header_params, is_valid_value},
+    limits::Limits,
+    line::read_line_limited,
     method::HttpMethod,
+    multipart::Multipart,
+    target::RequestTarget,
+    url::{Params, split_target},
+    version::HttpVersion,
 };
 
 #[derive(Debug)]
@@ -21,7 +29,7 @@ impl FromStr for HttpFirstRow {
     type Err = &'static str;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let parts: Vec<&str> = s.trim().split_whitespace().collect();
+        let parts: Vec<&str> = s.split_whitespace().collect();
 
         if parts.len() != 3 {
             return Err("Invalid request line format");
@@ -35,8 +43,8 @@ impl FromStr for HttpFirstRow {
     }
 }
 
-pub async fn get_body(
-    buffered_reader: &mut BufReader<OwnedReadHalf>,
+pub async fn get_body<R: AsyncBufRead + Unpin + ?Sized>(
+    buffered_reader: &mut R,
     content_length: usize,
 ) -> Result<Vec<u8>, io::Error> {
     let mut body = vec![0u8; content_length];
@@ -47,59 +55,747 @@ pub async fn get_body(
     Ok(body)
 }
 
+/// Splits a `name: value` line. Rejects lines not ending in CRLF, obs-folded
+/// continuation lines, whitespace before the colon and names that are not
+/// tokens, as well as values containing CR, LF or NUL, since proxies disagree
+/// on all of those.
+pub fn parse_header_line(line: &str) -> Option<(HttpHeaderName, &str)> {
+    let line = line.strip_suffix("\r\n")?;
+
+    let (key, value) = line.split_once(':')?;
+    let name = key.parse::<HttpHeaderName>().ok()?;
+
+    let value = value.trim_matches([' ', '\t']);
+    if !is_valid_value(value) {
+        return None;
+    }
+
+    Some((name, value))
+}
+
+fn is_valid_content_length(value: &str) -> bool {
+    !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) && value.parse::<usize>().is_ok()
+}
+
+/// Rejects requests whose body length different parsers could disagree on,
+/// the root of request smuggling. `chunked` is the only transfer coding we
+/// decode, so anything else is answered with 501.
+fn check_framing(headers: &HttpHeaders, version: HttpVersion) -> Result<(), HttpParseError> {
+    let lengths = headers.get_all(&HttpHeaderName::ContentLength).count();
+    if lengths > 1 {
+        return Err(HttpParseError::ConflictingFraming);
+    }
+
+    if !headers.contains(&HttpHeaderName::TransferEncoding) {
+        return Ok(());
+    }
+
+    if lengths > 0 || version < HttpVersion::Http11 {
+        return Err(HttpParseError::ConflictingFraming);
+    }
+
+    let codings = headers.transfer_encoding();
+    if codings.iter().any(|c| c != "chunked") {
+        return Err(HttpParseError::UnsupportedTransferCoding);
+    }
+
+    if codings.len() != 1 {
+        return Err(HttpParseError::ConflictingFraming);
+    }
+
+    Ok(())
+}
+
+/// HTTP/1.1 requests need exactly one valid `Host` header. HTTP/1.0 ones may
+/// leave it out, but must not send a broken or repeated one either.
+fn check_host(headers: &HttpHeaders, version: HttpVersion) -> Result<(), HttpParseError> {
+    let mut hosts = headers.get_all(&HttpHeaderName::Host);
+
+    match (hosts.next(), hosts.next()) {
+        (None, _) if version < HttpVersion::Http11 => Ok(()),
+        (Some(HttpHeaderValue::Host(..)), None) => Ok(()),
+        _ => Err(HttpParseError::InvalidHost),
+    }
+}
+
 #[derive(Debug)]
 pub struct HttpRequest {
     pub method: HttpMethod,
+    pub target: RequestTarget,
+    /// Percent-decoded and normalized path, without the query string. Empty
+    /// for authority-form and asterisk-form targets, which name no resource.
     pub path: String,
-    pub http_version: String, // e.g., "HTTP/1.1"
+    pub query: Params,
+    pub http_version: HttpVersion,
     pub headers: HttpHeaders,
-    pub body: Option<Vec<u8>>,
+    pub body: RequestBody,
 }
 
 impl HttpRequest {
-    pub async fn parse(
-        buffered_reader: &mut BufReader<OwnedReadHalf>,
-    ) -> Result<HttpRequest, io::Error> {
+    pub async fn parse<R: AsyncBufRead + Unpin>(
+        buffered_reader: &mut R,
+        limits: &Limits,
+    ) -> Result<HttpRequest, HttpParseError> {
+        let mut request = Self::parse_head(buffered_reader, limits).await?;
+        request.read_body(buffered_reader, limits).await?;
+
+        Ok(request)
+    }
+
+    /// Reads the request line and headers, leaving the body unread so the
+    /// caller can pick body limits based on the route.
+    pub async fn parse_head<R: AsyncBufRead + Unpin>(
+        buffered_reader: &mut R,
+        limits: &Limits,
+    ) -> Result<HttpRequest, HttpParseError> {
         let mut headers = HttpHeaders::new();
 
         let mut line = String::new();
 
-        let bytes_read = buffered_reader.read_line(&mut line).await?;
+        let bytes_read = read_line_limited(
+            buffered_reader,
+            &mut line,
+            limits.max_request_line,
+            HttpParseError::UriTooLong,
+        )
+        .await?;
         if bytes_read == 0 {
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, ""));
+            return Err(HttpParseError::ConnectionClosed);
         }
 
-        let first_row = line.parse::<HttpFirstRow>().unwrap();
-        let path = first_row.resource;
-        let http_version = first_row.protocol;
+        let first_row = line
+            .strip_suffix("\r\n")
+            .ok_or(HttpParseError::BadRequestLine)?
+            .parse::<HttpFirstRow>()
+            .map_err(|_| HttpParseError::BadRequestLine)?;
+
+        let http_version = first_row.protocol.parse::<HttpVersion>()?;
+
         let method = HttpMethod::from(first_row.method.as_str());
+        let target = first_row.resource.parse::<RequestTarget>()?;
+        target.check_method(&method)?;
+
+        let (path, query) = match target.path_and_query() {
+            Some(path_and_query) => split_target(path_and_query),
+            None => (String::new(), Params::new()),
+        };
+
+        let mut header_bytes = 0;
+        let mut header_count = 0;
 
         loop {
             line.clear();
 
-            let bytes_read = buffered_reader.read_line(&mut line).await?;
-            if bytes_read == 0 || line == "\r\n" {
+            let remaining = limits.max_header_bytes.saturating_sub(header_bytes);
+            let bytes_read = read_line_limited(
+                buffered_reader,
+                &mut line,
+                remaining,
+                HttpParseError::HeaderTooLarge,
+            )
+            .await?;
+            if bytes_read == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            if line == "\r\n" {
                 break;
             }
 
-            let mut parts = line.splitn(2, ':');
-            let key = parts.next().unwrap().trim();
-            let value = parts.next().unwrap().trim();
+            header_bytes += bytes_read;
+            header_count += 1;
+            if header_count > limits.max_header_count {
+                return Err(HttpParseError::HeaderTooLarge);
+            }
+
+            let (header, value) = parse_header_line(&line).ok_or(HttpParseError::BadHeader)?;
 
-            let header = HttpHeaderName::from(key);
+            if header == HttpHeaderName::ContentLength && !is_valid_content_length(value) {
+                return Err(HttpParseError::InvalidContentLength);
+            }
 
-            headers.add(header, value);
+            headers.append(header, value);
         }
 
-        let content_length = headers.content_length().unwrap_or(0);
-        let body = get_body(buffered_reader, content_length).await?;
+        check_framing(&headers, http_version)?;
+        check_host(&headers, http_version)?;
+
+        // The target's own authority wins over whatever Host says, so the
+        // handler sees the host the client actually addressed.
+        if let RequestTarget::Absolute { authority, .. } = &target {
+            headers.insert(HttpHeaderName::Host, authority);
+        }
 
         Ok(HttpRequest {
-            method: method,
-            path: path,
-            http_version: http_version,
-            headers: headers,
-            body: Some(body),
+            method,
+            target,
+            path,
+            query,
+            http_version,
+            headers,
+            body: RequestBody::empty(),
         })
     }
+
+    pub fn cookies(&self) -> CookieJar {
+        self.headers.cookies()
+    }
+
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query.get(name)
+    }
+
+    pub fn query_all(&self, name: &str) -> Vec<&str> {
+        self.query.get_all(name)
+    }
+
+    /// Decodes an `application/x-www-form-urlencoded` body into `T`, which
+    /// can be `Params` to keep every pair.
+    pub async fn form<T: DeserializeOwned>(&mut self) -> Result<T, BodyError> {
+        if !self
+            .headers
+            .has_media_type("application/x-www-form-urlencoded")
+        {
+            return Err(BodyError::UnsupportedMediaType);
+        }
+
+        let body = self.body.collect().await?;
+
+        serde_urlencoded::from_bytes(&body).map_err(|e| BodyError::Malformed(e.to_string()))
+    }
+
+    /// Decodes an `application/json` body into `T`.
+    pub async fn json<T: DeserializeOwned>(&mut self) -> Result<T, BodyError> {
+        if !self.headers.has_media_type("application/json") {
+            return Err(BodyError::UnsupportedMediaType);
+        }
+
+        let body = self.body.collect().await?;
+
+        serde_json::from_slice(&body).map_err(BodyError::InvalidJson)
+    }
+
+    /// Streams a `multipart/form-data` body part by part, using the boundary
+    /// from `Content-Type`.
+    pub fn multipart(&mut self) -> Result<Multipart<'_>, BodyError> {
+        if !self.headers.has_media_type("multipart/form-data") {
+            return Err(BodyError::UnsupportedMediaType);
+        }
+
+        let boundary = self
+            .headers
+            .content_type()
+            .map(header_params)
+            .unwrap_or_default()
+            .into_iter()
+            .find(|(name, _)| name == "boundary")
+            .map(|(_, value)| value)
+            .filter(|boundary| (1..=70).contains(&boundary.len()))
+            .ok_or_else(|| BodyError::Malformed("missing multipart boundary".to_string()))?;
+
+        Ok(Multipart::new(&mut self.body, &boundary))
+    }
+
+    pub fn body_framing(&self, limits: &Limits) -> Result<BodyFraming, HttpParseError> {
+        if self.headers.is_chunked() {
+            return Ok(BodyFraming::Chunked);
+        }
+
+        match self.headers.content_length().unwrap_or(0) {
+            0 => Ok(BodyFraming::Empty),
+            len if len > limits.max_body_size => Err(HttpParseError::BodyTooLarge),
+            len => Ok(BodyFraming::Length(len)),
+        }
+    }
+
+    /// Whether the client waits for `100 Continue` before sending the body.
+    /// HTTP/1.0 clients cannot ask for it, and any expectation other than
+    /// `100-continue` is rejected with 417.
+    pub fn expects_continue(&self) -> Result<bool, HttpParseError> {
+        if !self.headers.contains(&HttpHeaderName::Expect) {
+            return Ok(false);
+        }
+
+        let expects = self
+            .headers
+            .get_all(&HttpHeaderName::Expect)
+            .all(|value| value.as_str().trim().eq_ignore_ascii_case("100-continue"));
+        if !expects {
+            return Err(HttpParseError::ExpectationFailed);
+        }
+
+        Ok(self.http_version >= HttpVersion::Http11)
+    }
+
+    /// Reads the whole body into memory, merging chunked trailers into the
+    /// request headers.
+    pub async fn read_body<R: AsyncBufRead + Unpin>(
+        &mut self,
+        buffered_reader: &mut R,
+        limits: &Limits,
+    ) -> Result<(), HttpParseError> {
+        let body = match self.body_framing(limits)? {
+            BodyFraming::Empty => Vec::new(),
+            BodyFraming::Length(len) => get_body(buffered_reader, len).await?,
+            BodyFraming::Chunked => {
+                read_chunked_body(buffered_reader, &mut self.headers, limits).await?
+            }
+        };
+
+        self.body = RequestBody::from(body);
+
+        Ok(())
+    }
+
+    /// Lends the connection reader to the body, so the handler can stream it.
+    /// Get the reader back with `RequestBody::finish`.
+    pub fn attach_body(
+        &mut self,
+        reader: BoxedReader,
+        limits: &Limits,
+    ) -> Result<(), HttpParseError> {
+        let framing = self.body_framing(limits)?;
+        self.body = RequestBody::streaming(reader, framing, limits);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::http::status::StatusCode;
+
+    async fn parse(raw: &[u8]) -> Result<HttpRequest, HttpParseError> {
+        let mut reader = raw;
+        HttpRequest::parse(&mut reader, &Limits::default()).await
+    }
+
+    #[tokio::test]
+    async fn parses_request_with_query_and_body() {
+        let mut req =
+            parse(b"POST /json?x=1 HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nhello")
+                .await
+                .unwrap();
+
+        assert_eq!(req.path, "/json");
+        assert_eq!(req.query_param("x"), Some("1"));
+        assert_eq!(req.http_version, HttpVersion::Http11);
+        assert_eq!(req.headers.content_length(), Some(5));
+        assert_eq!(req.body.collect().await.unwrap(), b"hello");
+    }
+
+    #[tokio::test]
+    async fn parses_chunked_request() {
+        let mut req =
+            parse(b"POST / HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n")
+                .await
+                .unwrap();
+
+        assert_eq!(req.body.collect().await.unwrap(), b"abc");
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_requests() {
+        assert!(matches!(
+            parse(b"GET /\r\n\r\n").await,
+            Err(HttpParseError::BadRequestLine)
+        ));
+        assert!(matches!(
+            parse(b"GET / HTTP/2.0\r\n\r\n").await,
+            Err(HttpParseError::UnsupportedVersion)
+        ));
+        assert!(matches!(
+            parse(b"GET / HTTP/1.1\r\nHost: test\r\nContent-Length: ten\r\n\r\n").await,
+            Err(HttpParseError::InvalidContentLength)
+        ));
+        assert!(matches!(
+            parse(b"").await,
+            Err(HttpParseError::ConnectionClosed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn parses_request_target_forms() {
+        let req =
+            parse(b"GET http://example.com:8080/a/../json?x=1 HTTP/1.1\r\nHost: other\r\n\r\n")
+                .await
+                .unwrap();
+        assert_eq!(req.path, "/json");
+        assert_eq!(req.query_param("x"), Some("1"));
+        assert_eq!(req.headers.host(), Some(("example.com", Some(8080))));
+
+        let req = parse(b"CONNECT example.com:443 HTTP/1.1\r\nHost: test\r\n\r\n")
+            .await
+            .unwrap();
+        assert_eq!(req.target.authority(), Some("example.com:443"));
+        assert_eq!(req.path, "");
+
+        let req = parse(b"OPTIONS * HTTP/1.1\r\nHost: test\r\n\r\n")
+            .await
+            .unwrap();
+        assert_eq!(req.target, RequestTarget::Asterisk);
+
+        for raw in [
+            &b"GET * HTTP/1.1\r\nHost: test\r\n\r\n"[..],
+            b"GET example.com:443 HTTP/1.1\r\nHost: test\r\n\r\n",
+            b"CONNECT / HTTP/1.1\r\nHost: test\r\n\r\n",
+            b"GET json HTTP/1.1\r\nHost: test\r\n\r\n",
+        ] {
+            assert!(matches!(
+                parse(raw).await,
+                Err(HttpParseError::BadRequestLine)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn requires_single_valid_host() {
+        let req = parse(b"GET / HTTP/1.1\r\nHost: [::1]:7878\r\n\r\n")
+            .await
+            .unwrap();
+        assert_eq!(req.headers.host(), Some(("[::1]", Some(7878))));
+
+        let req = parse(b"GET / HTTP/1.0\r\n\r\n").await.unwrap();
+        assert_eq!(req.headers.host(), None);
+
+        for raw in [
+            &b"GET / HTTP/1.1\r\n\r\n"[..],
+            b"GET / HTTP/1.1\r\nHost: a\r\nHost: b\r\n\r\n",
+            b"GET / HTTP/1.1\r\nHost: a:port\r\n\r\n",
+            b"GET / HTTP/1.0\r\nHost: [::1\r\n\r\n",
+            b"GET / HTTP/1.0\r\nHost: user@a\r\n\r\n",
+        ] {
+            let err = parse(raw).await.unwrap_err();
+            assert!(matches!(err, HttpParseError::InvalidHost));
+            assert_eq!(err.status_code(), Some(StatusCode::BAD_REQUEST));
+        }
+    }
+
+    #[tokio::test]
+    async fn parses_cookies() {
+        let req = parse(b"GET / HTTP/1.1\r\nHost: test\r\nCookie: a=1; b=2\r\nCookie: c=3\r\n\r\n")
+            .await
+            .unwrap();
+
+        let cookies = req.cookies();
+        assert_eq!(cookies.get("b"), Some("2"));
+        assert_eq!(cookies.get("c"), Some("3"));
+    }
+
+    #[tokio::test]
+    async fn decodes_form_body() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Login {
+            user: String,
+            remember: bool,
+            attempts: u32,
+        }
+
+        let raw = b"POST /login HTTP/1.1\r\nHost: test\r\n\
+                    Content-Type: application/x-www-form-urlencoded; charset=UTF-8\r\n\
+                    Content-Length: 41\r\n\r\n\
+                    user=J%C3%B6rg+M&remember=true&attempts=3";
+
+        let mut req = parse(raw).await.unwrap();
+        let login: Login = req.form().await.unwrap();
+        assert_eq!(
+            login,
+            Login {
+                user: "Jörg M".to_string(),
+                remember: true,
+                attempts: 3,
+            }
+        );
+
+        let mut req = parse(raw).await.unwrap();
+        let params: Params = req.form().await.unwrap();
+        assert_eq!(params.get("user"), Some("Jörg M"));
+        assert_eq!(params.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_form_bodies() {
+        let mut req = parse(
+            b"POST / HTTP/1.1\r\nHost: test\r\nContent-Type: application/json\r\nContent-Length: 2\r\n\r\n{}",
+        )
+        .await
+        .unwrap();
+        let err = req.form::<Params>().await.unwrap_err();
+        assert_eq!(err.status_code(), Some(StatusCode::UNSUPPORTED_MEDIA_TYPE));
+
+        let mut req = parse(b"POST / HTTP/1.1\r\nHost: test\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: 10\r\n\r\nattempts=x")
+            .await
+            .unwrap();
+        let err = req.form::<HashMap<String, u32>>().await.unwrap_err();
+        assert_eq!(err.status_code(), Some(StatusCode::BAD_REQUEST));
+    }
+
+    #[tokio::test]
+    async fn decodes_json_body() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Greeting {
+            hello: String,
+        }
+
+        let mut req = parse(b"POST / HTTP/1.1\r\nHost: test\r\nContent-Type: application/json\r\nContent-Length: 17\r\n\r\n{\"hello\":\"world\"}")
+            .await
+            .unwrap();
+        let greeting: Greeting = req.json().await.unwrap();
+        assert_eq!(greeting.hello, "world");
+
+        let mut req = parse(b"POST / HTTP/1.1\r\nHost: test\r\nContent-Type: application/json\r\nContent-Length: 10\r\n\r\n{\"hello\":1")
+            .await
+            .unwrap();
+        let err = req.json::<Greeting>().await.unwrap_err();
+        assert_eq!(err.status_code(), Some(StatusCode::BAD_REQUEST));
+        assert!(matches!(err, BodyError::InvalidJson(e) if e.line() == 1 && e.column() == 10));
+
+        let mut req =
+            parse(b"POST / HTTP/1.1\r\nHost: test\r\nContent-Type: text/plain\r\nContent-Length: 2\r\n\r\n{}")
+                .await
+                .unwrap();
+        let err = req.json::<Greeting>().await.unwrap_err();
+        assert_eq!(err.status_code(), Some(StatusCode::UNSUPPORTED_MEDIA_TYPE));
+    }
+
+    #[tokio::test]
+    async fn reads_multipart_boundary_from_content_type() {
+        let mut req = parse(b"POST / HTTP/1.1\r\nHost: test\r\nContent-Type: multipart/form-data; boundary=\"a b\"\r\nContent-Length: 59\r\n\r\n--a b\r\nContent-Disposition: form-data; name=x\r\n\r\n1\r\n--a b--")
+            .await
+            .unwrap();
+        let mut multipart = req.multipart().unwrap();
+        let mut part = multipart.next_part().await.unwrap().unwrap();
+        assert_eq!(part.name(), Some("x"));
+        assert_eq!(part.text(16).await.unwrap(), "1");
+
+        let mut req =
+            parse(b"POST / HTTP/1.1\r\nHost: test\r\nContent-Type: multipart/form-data\r\n\r\n")
+                .await
+                .unwrap();
+        assert!(matches!(req.multipart(), Err(BodyError::Malformed(_))));
+
+        let mut req = parse(b"POST / HTTP/1.1\r\nHost: test\r\nContent-Type: text/plain\r\n\r\n")
+            .await
+            .unwrap();
+        assert!(matches!(
+            req.multipart(),
+            Err(BodyError::UnsupportedMediaType)
+        ));
+    }
+
+    #[tokio::test]
+    async fn detects_expect_continue() {
+        let req = parse(b"POST / HTTP/1.1\r\nHost: test\r\nExpect: 100-Continue\r\n\r\n")
+            .await
+            .unwrap();
+        assert!(req.expects_continue().unwrap());
+
+        let req = parse(b"POST / HTTP/1.0\r\nExpect: 100-continue\r\n\r\n")
+            .await
+            .unwrap();
+        assert!(!req.expects_continue().unwrap());
+
+        let req = parse(b"POST / HTTP/1.1\r\nHost: test\r\nExpect: teapot\r\n\r\n")
+            .await
+            .unwrap();
+        assert!(matches!(
+            req.expects_continue(),
+            Err(HttpParseError::ExpectationFailed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_smuggling_payloads() {
+        let payloads: [(&str, &[u8]); 17] = [
+            (
+                "conflicting content-length",
+                b"POST / HTTP/1.1\r\nHost: test\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\nhello!",
+            ),
+            (
+                "duplicated content-length",
+                b"POST / HTTP/1.1\r\nHost: test\r\nContent-Length: 5\r\nContent-Length: 5\r\n\r\nhello",
+            ),
+            (
+                "content-length list",
+                b"POST / HTTP/1.1\r\nHost: test\r\nContent-Length: 5, 5\r\n\r\nhello",
+            ),
+            (
+                "signed content-length",
+                b"POST / HTTP/1.1\r\nHost: test\r\nContent-Length: +5\r\n\r\nhello",
+            ),
+            (
+                "negative content-length",
+                b"POST / HTTP/1.1\r\nHost: test\r\nContent-Length: -1\r\n\r\n",
+            ),
+            (
+                "CL.TE",
+                b"POST / HTTP/1.1\r\nHost: test\r\nContent-Length: 6\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\nG",
+            ),
+            (
+                "TE.CL",
+                b"POST / HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n8\r\nSMUGGLED\r\n0\r\n\r\n",
+            ),
+            (
+                "chunked applied twice",
+                b"POST / HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: chunked, chunked\r\n\r\n0\r\n\r\n",
+            ),
+            (
+                "transfer-encoding in HTTP/1.0",
+                b"POST / HTTP/1.0\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+            ),
+            (
+                "space before colon",
+                b"POST / HTTP/1.1\r\nHost: test\r\nTransfer-Encoding : chunked\r\n\r\n0\r\n\r\n",
+            ),
+            (
+                "tab before colon",
+                b"POST / HTTP/1.1\r\nHost: test\r\nContent-Length\t: 5\r\n\r\nhello",
+            ),
+            (
+                "obs-fold",
+                b"POST / HTTP/1.1\r\nHost: test\r\nTransfer-Encoding:\r\n chunked\r\n\r\n0\r\n\r\n",
+            ),
+            (
+                "invalid name characters",
+                b"POST / HTTP/1.1\r\nHost: test\r\nContent\"Length: 5\r\n\r\nhello",
+            ),
+            (
+                "bare LF after request line",
+                b"POST / HTTP/1.1\nHost: test\r\nContent-Length: 5\r\n\r\nhello",
+            ),
+            (
+                "bare LF after header",
+                b"POST / HTTP/1.1\r\nHost: test\nContent-Length: 5\r\n\r\nhello",
+            ),
+            (
+                "bare LF ending the head",
+                b"POST / HTTP/1.1\r\nHost: test\r\nContent-Length: 5\r\n\nhello",
+            ),
+            (
+                "bare CR inside value",
+                b"POST / HTTP/1.1\r\nHost: test\r\nX-Foo: a\rTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+            ),
+        ];
+
+        for (name, raw) in payloads {
+            let res = parse(raw).await;
+            let status = res.as_ref().err().and_then(|e| e.status_code());
+
+            assert_eq!(
+                status,
+                Some(StatusCode::BAD_REQUEST),
+                "{} was accepted: {:?}",
+                name,
+                res
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_unsupported_transfer_codings() {
+        let payloads: [(&str, &[u8]); 4] = [
+            (
+                "TE.TE obfuscated second header",
+                b"POST / HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: x\r\n\r\n0\r\n\r\n",
+            ),
+            (
+                "unknown coding",
+                b"POST / HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: xchunked\r\n\r\n0\r\n\r\n",
+            ),
+            (
+                "gzip before chunked",
+                b"POST / HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: gzip, chunked\r\n\r\n0\r\n\r\n",
+            ),
+            (
+                "gzip in a second header",
+                b"POST / HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: gzip\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+            ),
+        ];
+
+        for (name, raw) in payloads {
+            let res = parse(raw).await;
+            let status = res.as_ref().err().and_then(|e| e.status_code());
+
+            assert_eq!(
+                status,
+                Some(StatusCode::NOT_IMPLEMENTED),
+                "{} was accepted: {:?}",
+                name,
+                res
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_smuggled_chunk_sizes() {
+        let payloads: [&[u8]; 7] = [
+            b"POST / HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: chunked\r\n\r\n0x5\r\nhello\r\n0\r\n\r\n",
+            b"POST / HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\n0\r\n\r\n",
+            b"POST / HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: chunked\r\n\r\n 5\r\nhello\r\n0\r\n\r\n",
+            b"POST / HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: chunked\r\n\r\n5 \r\nhello\r\n0\r\n\r\n",
+            b"POST / HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: chunked\r\n\r\n5;a\"b\r\nhello\r\n0\r\n\r\n",
+            b"POST / HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: chunked\r\n\r\n10000000000000001\r\nx\r\n0\r\n\r\n",
+            b"POST / HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhelloX\r\n0\r\n\r\n",
+        ];
+
+        for raw in payloads {
+            assert!(matches!(parse(raw).await, Err(HttpParseError::BadChunk)));
+        }
+    }
+
+    #[tokio::test]
+    async fn accepts_valid_framing() {
+        let mut req =
+            parse(b"POST / HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: Chunked\r\n\r\n2\r\nok\r\n0\r\n\r\n")
+                .await
+                .unwrap();
+        assert_eq!(req.body.collect().await.unwrap(), b"ok");
+
+        let mut req = parse(b"POST / HTTP/1.1\r\nHost: test\r\nContent-Length:2\r\n\r\nok")
+            .await
+            .unwrap();
+        assert_eq!(req.body.collect().await.unwrap(), b"ok");
+    }
+
+    #[tokio::test]
+    async fn enforces_limits() {
+        let limits = Limits {
+            max_header_count: 2,
+            max_body_size: 4,
+            ..Limits::default()
+        };
+
+        let mut reader: &[u8] = b"GET / HTTP/1.1\r\nHost: test\r\nA: 1\r\nB: 2\r\n\r\n";
+        let res = HttpRequest::parse(&mut reader, &limits).await;
+        assert!(matches!(res, Err(HttpParseError::HeaderTooLarge)));
+
+        let mut reader: &[u8] = b"POST / HTTP/1.1\r\nHost: test\r\nContent-Length: 5\r\n\r\nhello";
+        let res = HttpRequest::parse(&mut reader, &limits).await;
+        assert!(matches!(res, Err(HttpParseError::BodyTooLarge)));
+    }
+
+    #[test]
+    fn parses_header_line() {
+        let (name, value) = parse_header_line("Content-Type:  text/html \r\n").unwrap();
+
+        assert_eq!(name, HttpHeaderName::ContentType);
+        assert_eq!(value, "text/html");
+    }
+
+    #[test]
+    fn rejects_header_line_without_colon() {
+        assert!(parse_header_line("Content-Type text/html\r\n").is_none());
+        assert!(parse_header_line(": text/html\r\n").is_none());
+        assert!(parse_header_line("Content-Type: text/html\n").is_none());
+    }
+
+    #[test]
+    fn rejects_non_token_header_names() {
+        assert!(parse_header_line("Content-Type : text/html\r\n").is_none());
+        assert!(parse_header_line(" Content-Type: text/html\r\n").is_none());
+        assert!(parse_header_line("Content(Type): text/html\r\n").is_none());
+        assert!(parse_header_line("X-Custom_Header.1: ok\r\n").is_some());
+    }
 }
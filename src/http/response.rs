@@ -1,26 +1,477 @@
-use super::headers::{HttpHeaderName, HttpHeaders};
+use std::path::Path;
+
+use log::error;
+use serde::Serialize;
+use tokio::{
+    fs,
+    io::{self, AsyncWrite, AsyncWriteExt},
+};
+
+use super::{
+    body::{BodyFrame, ResponseBody},
+    chunked::{write_chunk, write_last_chunk},
+    cookie::Cookie,
+    date,
+    error::BodyError,
+    headers::{HttpHeaderName, HttpHeaderValue, HttpHeaders, is_valid_value},
+    status::StatusCode,
+    version::HttpVersion,
+};
 
 #[derive(Debug)]
 pub struct HttpResponse {
-    pub status_code: usize,
+    pub version: HttpVersion,
+    pub status_code: StatusCode,
     pub headers: HttpHeaders,
-    pub body: String,
+    pub body: ResponseBody,
+    /// Sent after a chunked body. Dropped when the body has a known length
+    /// or the client speaks HTTP/1.0.
+    pub trailers: HttpHeaders,
+}
+
+impl Default for HttpResponse {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl HttpResponse {
     pub fn new() -> HttpResponse {
         HttpResponse {
-            status_code: 200,
+            version: HttpVersion::Http11,
+            status_code: StatusCode::OK,
             headers: HttpHeaders::new(),
-            body: String::new(),
+            body: ResponseBody::new(),
+            trailers: HttpHeaders::new(),
+        }
+    }
+
+    /// An HTML page.
+    pub fn html(body: impl Into<ResponseBody>) -> HttpResponse {
+        Self::with_body("text/html; charset=utf-8", body)
+    }
+
+    /// A plain text body.
+    pub fn text(body: impl Into<ResponseBody>) -> HttpResponse {
+        Self::with_body("text/plain; charset=utf-8", body)
+    }
+
+    /// `value` serialized as JSON. A value that cannot be serialized gives
+    /// an empty 500 instead.
+    pub fn json<T: Serialize + ?Sized>(value: &T) -> HttpResponse {
+        let mut res = Self::new();
+        res.set_json(value);
+        res
+    }
+
+    /// Points the client to `location`, e.g. with `SEE_OTHER` after a form
+    /// post. A location with CR, LF or NUL, which could inject headers, gives
+    /// 500 instead.
+    pub fn redirect(status: StatusCode, location: &str) -> HttpResponse {
+        if !is_valid_value(location) {
+            error!("Refusing to redirect to a location with CR, LF or NUL");
+            return Self::error(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error");
+        }
+
+        Self::new()
+            .with_status(status)
+            .with_header(HttpHeaderName::Location, location)
+    }
+
+    pub fn no_content() -> HttpResponse {
+        Self::new().with_status(StatusCode::NO_CONTENT)
+    }
+
+    /// The contents of `path`, typed by its extension and streamed rather
+    /// than read into memory. A missing file or a directory gives 404 and
+    /// any other failure to open it 500.
+    pub async fn file(path: impl AsRef<Path>) -> HttpResponse {
+        let path = path.as_ref();
+
+        let opened = match fs::File::open(path).await {
+            Ok(file) => file.metadata().await.map(|meta| (file, meta)),
+            Err(e) => Err(e),
+        };
+
+        match opened {
+            Ok((file, meta)) if meta.is_file() => {
+                Self::with_body(content_type_for(path), ResponseBody::reader(file))
+            }
+            Ok(_) => Self::error(StatusCode::NOT_FOUND, "Not found"),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Self::error(StatusCode::NOT_FOUND, "Not found")
+            }
+            Err(e) => {
+                error!("Failed to open {}: {}", path.display(), e);
+                Self::error(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+            }
         }
     }
 
+    /// An error status with `message` as plain text.
+    pub fn error(status: StatusCode, message: &str) -> HttpResponse {
+        Self::text(message.to_string()).with_status(status)
+    }
+
+    pub fn with_status(mut self, status: StatusCode) -> HttpResponse {
+        self.status_code = status;
+        self
+    }
+
+    /// Sets `header`, replacing any value it already had.
+    pub fn with_header(mut self, header: HttpHeaderName, value: &str) -> HttpResponse {
+        self.add_header(header, value);
+        self
+    }
+
+    fn with_body(content_type: &str, body: impl Into<ResponseBody>) -> HttpResponse {
+        let mut res = Self::new().with_header(HttpHeaderName::ContentType, content_type);
+        res.body = body.into();
+        res
+    }
+
+    /// Sets `header`, replacing any value it already had.
     pub fn add_header(&mut self, header: HttpHeaderName, value: &str) {
-        self.headers.add(header, value);
+        self.headers.insert(header, value);
+    }
+
+    /// Adds another value for a header that may repeat, such as `Vary`.
+    pub fn append_header(&mut self, header: HttpHeaderName, value: &str) {
+        self.headers.append(header, value);
+    }
+
+    pub fn remove_header(&mut self, header: &HttpHeaderName) {
+        self.headers.remove(header);
+    }
+
+    /// Adds `Date` and, unless `server` is `None`, `Server`. Values the
+    /// handler set itself are kept.
+    pub fn add_default_headers(&mut self, server: Option<&str>) {
+        if !self.headers.contains(&HttpHeaderName::Date) {
+            self.headers.insert_value(
+                HttpHeaderName::Date,
+                HttpHeaderValue::Raw(date::now_imf_fixdate()),
+            );
+        }
+
+        if let Some(server) = server
+            && !self.headers.contains(&HttpHeaderName::Server)
+        {
+            self.add_header(HttpHeaderName::Server, server);
+        }
+    }
+
+    /// Serializes `value` as the JSON body, keeping the status and headers
+    /// set so far. A value that cannot be serialized turns the response into
+    /// an empty 500.
+    pub fn set_json<T: Serialize + ?Sized>(&mut self, value: &T) {
+        match serde_json::to_vec(value) {
+            Ok(body) => {
+                self.add_header(HttpHeaderName::ContentType, "application/json");
+                self.body = body.into();
+            }
+            Err(e) => {
+                error!("Failed to serialize response body: {}", e);
+                self.status_code = StatusCode::INTERNAL_SERVER_ERROR;
+                self.body = ResponseBody::new();
+            }
+        }
+    }
+
+    /// Adds a `Set-Cookie` line for `cookie`. Cookies that could break the
+    /// header, e.g. with a `;` or line break in the value, are dropped.
+    pub fn set_cookie(&mut self, cookie: Cookie) {
+        if !cookie.is_valid() {
+            error!("Dropping invalid cookie: {}", cookie.name());
+            return;
+        }
+
+        self.headers
+            .append(HttpHeaderName::SetCookie, &cookie.to_string());
+    }
+
+    /// Length of a buffered body, or `None` if it is streamed.
+    pub fn content_length(&self) -> Option<usize> {
+        self.body.as_bytes().map(<[u8]>::len)
+    }
+
+    /// Writes the response. Streaming bodies are chunked if the headers say
+    /// so and written as they come otherwise, leaving the caller to close
+    /// the connection to mark their end.
+    pub async fn write_to<W: AsyncWrite + Unpin>(
+        &mut self,
+        writer: &mut W,
+    ) -> Result<(), io::Error> {
+        // The space before the reason phrase is required even when there
+        // is no phrase to send.
+        let head = format!(
+            "{} {} {}\r\n{}\r\n\r\n",
+            self.version,
+            self.status_code.as_u16(),
+            self.status_code.canonical_reason().unwrap_or(""),
+            self.headers.as_str(),
+        );
+
+        if let Some(body) = self.body.as_bytes() {
+            let mut response = Vec::with_capacity(head.len() + body.len());
+            response.extend_from_slice(head.as_bytes());
+            response.extend_from_slice(body);
+
+            return writer.write_all(&response).await;
+        }
+
+        writer.write_all(head.as_bytes()).await?;
+        writer.flush().await?;
+
+        let chunked = self.headers.is_chunked();
+        let mut trailers = std::mem::take(&mut self.trailers);
+
+        while let Some(frame) = self.body.next_frame().await {
+            match frame? {
+                BodyFrame::Data(data) if chunked => write_chunk(writer, &data).await?,
+                BodyFrame::Data(data) => writer.write_all(&data).await?,
+                BodyFrame::Trailers(more) => trailers.extend(more),
+            }
+            writer.flush().await?;
+        }
+
+        if chunked {
+            write_last_chunk(writer, &trailers).await?;
+        }
+
+        writer.flush().await
+    }
+}
+
+/// Answers a body the handler could not decode with a JSON error object,
+/// e.g. `{"error":"Body too large"}` and 413. Failures on our side are logged
+/// and reported without their details.
+impl From<BodyError> for HttpResponse {
+    fn from(err: BodyError) -> Self {
+        let status = err.status_code().unwrap_or(StatusCode::BAD_REQUEST);
+
+        let message = if status.is_server_error() {
+            error!("Failed to handle request body: {}", err);
+            "Internal server error".to_string()
+        } else {
+            err.to_string()
+        };
+
+        Self::json(&serde_json::json!({ "error": message })).with_status(status)
+    }
+}
+
+/// Media type for a file served by `HttpResponse::file`.
+fn content_type_for(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+
+    match extension.as_deref() {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js" | "mjs") => "text/javascript; charset=utf-8",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("csv") => "text/csv; charset=utf-8",
+        Some("xml") => "application/xml",
+        Some("json") => "application/json",
+        Some("pdf") => "application/pdf",
+        Some("wasm") => "application/wasm",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        Some("ico") => "image/x-icon",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::http::cookie::SameSite;
+
+    #[tokio::test]
+    async fn writes_headers_in_order_without_duplicates() {
+        let mut res = HttpResponse::new();
+        res.add_header(HttpHeaderName::ContentType, "text/plain");
+        res.add_header(HttpHeaderName::ContentLength, "99");
+        res.append_header(HttpHeaderName::Vary, "Accept");
+        res.append_header(HttpHeaderName::Vary, "Cookie");
+        res.add_header(HttpHeaderName::ContentLength, "0");
+
+        let mut out = Vec::new();
+        res.write_to(&mut out).await.unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 0\r\nVary: Accept\r\nVary: Cookie\r\n\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_reason_phrase_on_status_line() {
+        let mut res = HttpResponse::new();
+        res.status_code = StatusCode::NOT_FOUND;
+
+        let mut out = Vec::new();
+        res.write_to(&mut out).await.unwrap();
+        assert!(out.starts_with(b"HTTP/1.1 404 Not Found\r\n"));
+
+        res.status_code = StatusCode::from_u16(599).unwrap();
+
+        let mut out = Vec::new();
+        res.write_to(&mut out).await.unwrap();
+        assert!(out.starts_with(b"HTTP/1.1 599 \r\n"));
+    }
+
+    #[tokio::test]
+    async fn writes_binary_body_unchanged() {
+        let payload: &[u8] = &[0x1f, 0x8b, 0x08, 0x00, 0xff, 0xfe, b'\r', b'\n'];
+        let mut res = HttpResponse::new();
+        res.body = payload.into();
+
+        let mut out = Vec::new();
+        res.write_to(&mut out).await.unwrap();
+
+        assert_eq!(res.content_length(), Some(payload.len()));
+        assert!(out.ends_with(payload));
+    }
+
+    #[tokio::test]
+    async fn emits_one_line_per_cookie() {
+        let mut res = HttpResponse::new();
+        res.set_cookie(Cookie::new("a", "1").http_only());
+        res.set_cookie(Cookie::new("b", "2").same_site(SameSite::Strict));
+        res.set_cookie(Cookie::new("c", "3\r\nX-Evil: 1"));
+
+        let mut out = Vec::new();
+        res.write_to(&mut out).await.unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.contains("\r\nSet-Cookie: a=1; HttpOnly\r\n"));
+        assert!(out.contains("\r\nSet-Cookie: b=2; SameSite=Strict\r\n"));
+        assert!(!out.contains("X-Evil"));
     }
 
-    pub fn content_length(&self) -> usize {
-        self.body.len()
+    #[test]
+    fn serializes_json_body() {
+        let res = HttpResponse::json(&HashMap::from([("hello", "world")]));
+
+        assert_eq!(res.status_code, 200);
+        assert_eq!(res.headers.content_type(), Some("application/json"));
+        assert_eq!(res.body, r#"{"hello":"world"}"#);
+    }
+
+    #[test]
+    fn maps_serialization_failure_to_500() {
+        // JSON object keys must be strings.
+        let res = HttpResponse::json(&HashMap::from([((1, 2), "x")]));
+
+        assert_eq!(res.status_code, 500);
+        assert_eq!(res.body, "");
+    }
+
+    #[test]
+    fn sets_json_body_in_place() {
+        let mut res = HttpResponse::new().with_status(StatusCode::CREATED);
+        res.set_cookie(Cookie::new("session", "abc"));
+
+        res.set_json(&HashMap::from([("id", 7)]));
+
+        assert_eq!(res.status_code, StatusCode::CREATED);
+        assert!(res.headers.contains(&HttpHeaderName::SetCookie));
+        assert_eq!(res.headers.content_type(), Some("application/json"));
+        assert_eq!(res.body, r#"{"id":7}"#);
+    }
+
+    #[test]
+    fn builds_common_responses() {
+        let res = HttpResponse::html("<p>hi</p>");
+        assert_eq!(res.headers.content_type(), Some("text/html; charset=utf-8"));
+        assert_eq!(res.body, "<p>hi</p>");
+
+        let res = HttpResponse::redirect(StatusCode::SEE_OTHER, "/done");
+        assert_eq!(res.status_code, 303);
+        assert_eq!(
+            res.headers
+                .get(&HttpHeaderName::Location)
+                .map(|v| v.as_str()),
+            Some("/done".to_string())
+        );
+
+        let res = HttpResponse::error(StatusCode::FORBIDDEN, "No access")
+            .with_header(HttpHeaderName::CacheControl, "no-store");
+        assert_eq!(res.status_code, StatusCode::FORBIDDEN);
+        assert_eq!(
+            res.headers.content_type(),
+            Some("text/plain; charset=utf-8")
+        );
+        assert_eq!(res.body, "No access");
+
+        assert_eq!(HttpResponse::no_content().status_code, 204);
+    }
+
+    #[test]
+    fn answers_body_errors_with_json() {
+        let res = HttpResponse::from(BodyError::TooLarge);
+        assert_eq!(res.status_code, StatusCode::CONTENT_TOO_LARGE);
+        assert_eq!(res.headers.content_type(), Some("application/json"));
+        assert_eq!(res.body, r#"{"error":"Body too large"}"#);
+
+        let res: HttpResponse = BodyError::Malformed("missing field".to_string()).into();
+        assert_eq!(res.status_code, StatusCode::BAD_REQUEST);
+        assert_eq!(res.body, r#"{"error":"Malformed body: missing field"}"#);
+
+        let res = HttpResponse::from(BodyError::Storage(io::Error::other("disk full")));
+        assert_eq!(res.status_code, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(res.body, r#"{"error":"Internal server error"}"#);
+    }
+
+    #[tokio::test]
+    async fn never_emits_location_with_crlf() {
+        let mut res =
+            HttpResponse::redirect(StatusCode::FOUND, "/next\r\nSet-Cookie: session=evil");
+        assert_eq!(res.status_code, StatusCode::INTERNAL_SERVER_ERROR);
+
+        res.add_header(HttpHeaderName::Location, "/a\r\nX-Evil: 1");
+
+        let mut out = Vec::new();
+        res.write_to(&mut out).await.unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(!out.contains("Location"));
+        assert!(!out.contains("Set-Cookie"));
+        assert!(!out.contains("X-Evil"));
+    }
+
+    #[tokio::test]
+    async fn serves_files_by_extension() {
+        let mut res = HttpResponse::file("public/index.html").await;
+        assert_eq!(res.status_code, StatusCode::OK);
+        assert_eq!(res.headers.content_type(), Some("text/html; charset=utf-8"));
+        assert!(res.body.is_streaming());
+
+        let mut body = Vec::new();
+        while let Some(BodyFrame::Data(data)) = res.body.next_frame().await.transpose().unwrap() {
+            body.extend_from_slice(&data);
+        }
+        assert_eq!(body, std::fs::read("public/index.html").unwrap());
+
+        let res = HttpResponse::file("public/missing.png").await;
+        assert_eq!(res.status_code, StatusCode::NOT_FOUND);
+
+        let res = HttpResponse::file("public").await;
+        assert_eq!(res.status_code, StatusCode::NOT_FOUND);
+
+        assert_eq!(content_type_for(Path::new("a/b.PNG")), "image/png");
+        assert_eq!(
+            content_type_for(Path::new("data")),
+            "application/octet-stream"
+        );
     }
 }
@@ -0,0 +1,292 @@
+use std::{fmt, net::Ipv6Addr, str::FromStr};
+
+use super::{error::HttpParseError, method::HttpMethod};
+
+/// The request target of the request line, in one of the four RFC 9112
+/// forms.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestTarget {
+    /// `/path?query`, what clients send to origin servers.
+    Origin(String),
+    /// `http://host/path?query`, what clients send to proxies. `path` is the
+    /// origin-form part and defaults to `/`.
+    Absolute {
+        scheme: String,
+        authority: String,
+        path: String,
+    },
+    /// `host:port`, only used with CONNECT.
+    Authority(String),
+    /// `*`, only used with a server-wide OPTIONS.
+    Asterisk,
+}
+
+impl RequestTarget {
+    /// The origin-form `path?query` the router matches on, if the target has
+    /// one.
+    pub fn path_and_query(&self) -> Option<&str> {
+        match self {
+            RequestTarget::Origin(path) | RequestTarget::Absolute { path, .. } => Some(path),
+            RequestTarget::Authority(_) | RequestTarget::Asterisk => None,
+        }
+    }
+
+    /// Host and port named by the target itself, which take precedence over
+    /// the `Host` header.
+    pub fn authority(&self) -> Option<&str> {
+        match self {
+            RequestTarget::Absolute { authority, .. } | RequestTarget::Authority(authority) => {
+                Some(authority)
+            }
+            RequestTarget::Origin(_) | RequestTarget::Asterisk => None,
+        }
+    }
+
+    /// Rejects forms the method cannot be used with: CONNECT takes
+    /// authority-form and nothing else does, and only OPTIONS takes `*`.
+    pub fn check_method(&self, method: &HttpMethod) -> Result<(), HttpParseError> {
+        let allowed = match self {
+            RequestTarget::Authority(_) => matches!(method, HttpMethod::CONNECT),
+            RequestTarget::Asterisk => matches!(method, HttpMethod::OPTIONS),
+            RequestTarget::Origin(_) | RequestTarget::Absolute { .. } => {
+                !matches!(method, HttpMethod::CONNECT)
+            }
+        };
+
+        if allowed {
+            Ok(())
+        } else {
+            Err(HttpParseError::BadRequestLine)
+        }
+    }
+}
+
+impl FromStr for RequestTarget {
+    type Err = HttpParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "*" {
+            return Ok(RequestTarget::Asterisk);
+        }
+
+        if s.starts_with('/') {
+            return Ok(RequestTarget::Origin(s.to_string()));
+        }
+
+        if let Some((scheme, rest)) = s.split_once("://")
+            && is_scheme(scheme)
+        {
+            let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+            let (authority, path) = rest.split_at(end);
+            if parse_authority(authority).is_none_or(|(host, _)| host.is_empty()) {
+                return Err(HttpParseError::BadRequestLine);
+            }
+
+            let path = if path.starts_with('/') {
+                path.to_string()
+            } else {
+                format!("/{}", path)
+            };
+
+            return Ok(RequestTarget::Absolute {
+                scheme: scheme.to_ascii_lowercase(),
+                authority: authority.to_string(),
+                path,
+            });
+        }
+
+        // Authority-form always carries a port, which also tells it apart
+        // from a bare scheme such as `mailto:`.
+        if let Some((host, Some(_))) = parse_authority(s)
+            && !host.is_empty()
+        {
+            return Ok(RequestTarget::Authority(s.to_string()));
+        }
+
+        Err(HttpParseError::BadRequestLine)
+    }
+}
+
+impl fmt::Display for RequestTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestTarget::Origin(path) => write!(f, "{}", path),
+            RequestTarget::Absolute {
+                scheme,
+                authority,
+                path,
+            } => write!(f, "{}://{}{}", scheme, authority, path),
+            RequestTarget::Authority(authority) => write!(f, "{}", authority),
+            RequestTarget::Asterisk => write!(f, "*"),
+        }
+    }
+}
+
+fn is_scheme(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic())
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"+-.".contains(&b))
+}
+
+/// Splits an authority into host and port, as used by `Host` and by
+/// absolute-form and authority-form targets. IPv6 literals keep their
+/// brackets and an empty port counts as none. Userinfo is rejected, since
+/// HTTP URIs must not carry it.
+pub fn parse_authority(s: &str) -> Option<(String, Option<u16>)> {
+    let (host, port) = if let Some(literal) = s.strip_prefix('[') {
+        let end = literal.find(']')?;
+        literal[..end].parse::<Ipv6Addr>().ok()?;
+
+        let (host, rest) = s.split_at(end + 2);
+        let port = match rest {
+            "" => None,
+            _ => Some(rest.strip_prefix(':')?),
+        };
+        (host, port)
+    } else {
+        let (host, port) = match s.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (s, None),
+        };
+        if !is_reg_name(host) {
+            return None;
+        }
+        (host, port)
+    };
+
+    let port = match port {
+        None | Some("") => None,
+        Some(port) if port.bytes().all(|b| b.is_ascii_digit()) => Some(port.parse().ok()?),
+        Some(_) => return None,
+    };
+
+    Some((host.to_string(), port))
+}
+
+/// RFC 3986 reg-name, which also covers IPv4 addresses.
+fn is_reg_name(s: &str) -> bool {
+    let bytes = s.as_bytes();
+
+    bytes.iter().enumerate().all(|(i, b)| match b {
+        b'%' => {
+            bytes.len() > i + 2
+                && bytes[i + 1].is_ascii_hexdigit()
+                && bytes[i + 2].is_ascii_hexdigit()
+        }
+        _ => b.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=".contains(b),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_all_target_forms() {
+        assert_eq!(
+            "/json?x=1".parse::<RequestTarget>().unwrap(),
+            RequestTarget::Origin("/json?x=1".to_string())
+        );
+        assert_eq!(
+            "HTTP://example.com:8080/json?x=1"
+                .parse::<RequestTarget>()
+                .unwrap(),
+            RequestTarget::Absolute {
+                scheme: "http".to_string(),
+                authority: "example.com:8080".to_string(),
+                path: "/json?x=1".to_string(),
+            }
+        );
+        assert_eq!(
+            "http://example.com?x=1".parse::<RequestTarget>().unwrap(),
+            RequestTarget::Absolute {
+                scheme: "http".to_string(),
+                authority: "example.com".to_string(),
+                path: "/?x=1".to_string(),
+            }
+        );
+        assert_eq!(
+            "[::1]:443".parse::<RequestTarget>().unwrap(),
+            RequestTarget::Authority("[::1]:443".to_string())
+        );
+        assert_eq!(
+            "*".parse::<RequestTarget>().unwrap(),
+            RequestTarget::Asterisk
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_targets() {
+        for target in [
+            "json",
+            "example.com",
+            "example.com:http",
+            ":443",
+            "http://",
+            "http://user@example.com/",
+            "1http://example.com/",
+            "**",
+        ] {
+            assert!(target.parse::<RequestTarget>().is_err(), "{}", target);
+        }
+    }
+
+    #[test]
+    fn parses_authorities() {
+        assert_eq!(
+            parse_authority("example.com:8080"),
+            Some(("example.com".to_string(), Some(8080)))
+        );
+        assert_eq!(
+            parse_authority("[::1]:7878"),
+            Some(("[::1]".to_string(), Some(7878)))
+        );
+        assert_eq!(
+            parse_authority("[2001:db8::7]"),
+            Some(("[2001:db8::7]".to_string(), None))
+        );
+        assert_eq!(
+            parse_authority("127.0.0.1:"),
+            Some(("127.0.0.1".to_string(), None))
+        );
+        assert_eq!(parse_authority(""), Some((String::new(), None)));
+        assert_eq!(
+            parse_authority("ex%41mple.com"),
+            Some(("ex%41mple.com".to_string(), None))
+        );
+
+        for s in [
+            "[::1",
+            "[not-ip]:80",
+            "[::1]80",
+            "::1",
+            "host:99999",
+            "host:+80",
+            "user@host",
+            "bad host",
+            "ex%4",
+        ] {
+            assert_eq!(parse_authority(s), None, "{}", s);
+        }
+    }
+
+    #[test]
+    fn checks_form_against_method() {
+        let authority = RequestTarget::Authority("example.com:443".to_string());
+        let origin = RequestTarget::Origin("/".to_string());
+
+        assert!(authority.check_method(&HttpMethod::CONNECT).is_ok());
+        assert!(authority.check_method(&HttpMethod::GET).is_err());
+        assert!(origin.check_method(&HttpMethod::CONNECT).is_err());
+        assert!(
+            RequestTarget::Asterisk
+                .check_method(&HttpMethod::OPTIONS)
+                .is_ok()
+        );
+        assert!(
+            RequestTarget::Asterisk
+                .check_method(&HttpMethod::GET)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use serde::Deserialize;
+
+/// Ordered multimap of `application/x-www-form-urlencoded` pairs, used for
+/// both query strings and form bodies.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "Vec<(String, String)>")]
+pub struct Params {
+    pairs: Vec<(String, String)>,
+}
+
+impl Params {
+    pub fn new() -> Self {
+        Params { pairs: Vec::new() }
+    }
+
+    pub fn parse(s: &str) -> Self {
+        let pairs = s
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let mut kv = pair.splitn(2, '=');
+                let key = kv.next().unwrap_or("");
+                let value = kv.next().unwrap_or("");
+                (decode_component(key), decode_component(value))
+            })
+            .collect();
+
+        Params { pairs }
+    }
+
+    /// First value for `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Every value for `key`, in the order they were sent.
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.pairs
+            .iter()
+            .filter(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+
+    pub fn add(&mut self, key: &str, value: &str) {
+        self.pairs.push((key.to_string(), value.to_string()));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+}
+
+impl From<Vec<(String, String)>> for Params {
+    fn from(pairs: Vec<(String, String)>) -> Self {
+        Params { pairs }
+    }
+}
+
+/// Decodes a form component, where `+` stands for a space.
+fn decode_component(s: &str) -> String {
+    percent_decode(&s.replace('+', " "))
+}
+
+/// Decodes `%XX` escapes. Malformed escapes are kept verbatim and invalid
+/// UTF-8 is replaced, so this never fails.
+pub fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && i + 2 < bytes.len()
+            && let (Some(hi), Some(lo)) = (hex_value(bytes[i + 1]), hex_value(bytes[i + 2]))
+        {
+            decoded.push(hi << 4 | lo);
+            i += 3;
+            continue;
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Resolves `.`/`..` segments and repeated slashes, then percent-decodes
+/// each segment, so the result always starts with `/` and never climbs above
+/// it. Segments are split before decoding and an encoded slash stays `%2F`,
+/// so `/a%2F..%2Fadmin` cannot turn into `/admin`.
+pub fn normalize_path(path: &str) -> String {
+    let mut segments: Vec<String> = Vec::new();
+    let mut trailing_slash = false;
+
+    for segment in path.split('/') {
+        let decoded = percent_decode(segment);
+        trailing_slash = matches!(decoded.as_str(), "" | "." | "..");
+
+        match decoded.as_str() {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            s => segments.push(s.replace('/', "%2F")),
+        }
+    }
+
+    let mut normalized = format!("/{}", segments.join("/"));
+    if trailing_slash && !segments.is_empty() {
+        normalized.push('/');
+    }
+
+    normalized
+}
+
+/// Splits an origin-form request target into its normalized path and query.
+pub fn split_target(target: &str) -> (String, Params) {
+    let target = target.split('#').next().unwrap_or("");
+
+    match target.split_once('?') {
+        Some((path, query)) => (normalize_path(path), Params::parse(query)),
+        None => (normalize_path(target), Params::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(percent_decode("a%20b%2Fc"), "a b/c");
+        assert_eq!(percent_decode("%E2%9C%93"), "✓");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+    }
+
+    #[test]
+    fn parses_query_multimap() {
+        let params = Params::parse("x=1&tag=a&tag=b+c&empty&name=J%C3%B6rg");
+
+        assert_eq!(params.get("x"), Some("1"));
+        assert_eq!(params.get_all("tag"), vec!["a", "b c"]);
+        assert_eq!(params.get("empty"), Some(""));
+        assert_eq!(params.get("name"), Some("Jörg"));
+        assert_eq!(params.get("missing"), None);
+        assert_eq!(params.len(), 5);
+    }
+
+    #[test]
+    fn normalizes_paths() {
+        assert_eq!(normalize_path("/"), "/");
+        assert_eq!(normalize_path(""), "/");
+        assert_eq!(normalize_path("/json"), "/json");
+        assert_eq!(normalize_path("//a/./b/../c"), "/a/c");
+        assert_eq!(normalize_path("/a/b/"), "/a/b/");
+        assert_eq!(normalize_path("/../../etc/passwd"), "/etc/passwd");
+        assert_eq!(normalize_path("/a/%2e%2e/b"), "/b");
+        assert_eq!(normalize_path("/hello%20world"), "/hello world");
+        assert_eq!(normalize_path("/a%2F..%2Fadmin"), "/a%2F..%2Fadmin");
+        assert_eq!(normalize_path("/a/b%2fc/.."), "/a/");
+        assert_eq!(normalize_path("/a/%2E/b/%2e%2E/"), "/a/");
+    }
+
+    #[test]
+    fn splits_target_into_path_and_query() {
+        let (path, query) = split_target("/json?x=1&y=%20#frag");
+
+        assert_eq!(path, "/json");
+        assert_eq!(query.get("x"), Some("1"));
+        assert_eq!(query.get("y"), Some(" "));
+    }
+}
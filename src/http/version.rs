@@ -0,0 +1,102 @@
+use std::{fmt, str::FromStr};
+
+use super::error::HttpParseError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HttpVersion {
+    Http10,
+    Http11,
+}
+
+impl FromStr for HttpVersion {
+    type Err = HttpParseError;
+
+    /// Well-formed versions other than 1.0 and 1.1 are answered with 505,
+    /// anything else is a malformed request line.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "HTTP/1.0" => Ok(HttpVersion::Http10),
+            "HTTP/1.1" => Ok(HttpVersion::Http11),
+            _ => {
+                let digits = s
+                    .strip_prefix("HTTP/")
+                    .ok_or(HttpParseError::BadRequestLine)?;
+
+                let well_formed = match digits.split_once('.') {
+                    Some((major, minor)) => {
+                        major.len() == 1
+                            && minor.len() == 1
+                            && major
+                                .chars()
+                                .chain(minor.chars())
+                                .all(|c| c.is_ascii_digit())
+                    }
+                    None => digits.len() == 1 && digits.chars().all(|c| c.is_ascii_digit()),
+                };
+
+                if well_formed {
+                    Err(HttpParseError::UnsupportedVersion)
+                } else {
+                    Err(HttpParseError::BadRequestLine)
+                }
+            }
+        }
+    }
+}
+
+impl HttpVersion {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HttpVersion::Http10 => "HTTP/1.0",
+            HttpVersion::Http11 => "HTTP/1.1",
+        }
+    }
+
+    /// Whether connections stay open unless the client asks otherwise.
+    pub fn is_persistent_by_default(&self) -> bool {
+        *self >= HttpVersion::Http11
+    }
+}
+
+impl fmt::Display for HttpVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_supported_versions() {
+        assert_eq!(
+            "HTTP/1.0".parse::<HttpVersion>().unwrap(),
+            HttpVersion::Http10
+        );
+        assert_eq!(
+            "HTTP/1.1".parse::<HttpVersion>().unwrap(),
+            HttpVersion::Http11
+        );
+    }
+
+    #[test]
+    fn distinguishes_unsupported_from_malformed() {
+        assert!(matches!(
+            "HTTP/2.0".parse::<HttpVersion>(),
+            Err(HttpParseError::UnsupportedVersion)
+        ));
+        assert!(matches!(
+            "HTTP/3".parse::<HttpVersion>(),
+            Err(HttpParseError::UnsupportedVersion)
+        ));
+        assert!(matches!(
+            "HTTP/1.1.1".parse::<HttpVersion>(),
+            Err(HttpParseError::BadRequestLine)
+        ));
+        assert!(matches!(
+            "http/1.1".parse::<HttpVersion>(),
+            Err(HttpParseError::BadRequestLine)
+        ));
+    }
+}
@@ -1,15 +1,14 @@
-use clap::{Parser, command};
-use http::{headers::HttpHeaderName, request::HttpRequest, response::HttpResponse};
+use clap::Parser;
+use http::{request::HttpRequest, response::HttpResponse};
 use routing::router::Router;
 use serde::{Deserialize, Serialize};
 use server::server::Server;
-use tokio::{fs::File, io::AsyncReadExt};
 
 pub mod http;
 pub mod routing;
 pub mod server;
 
-use log::{Level, info};
+use log::{Level, info, warn};
 
 /// Rust async TCP/HTTP server
 #[derive(Parser, Debug)]
@@ -18,26 +17,30 @@ pub struct Args {
     // Use keep alive or force the connection to close
     #[arg(short, long)]
     use_keep_alive: bool,
+
+    // Number of pipelined requests per connection handled concurrently,
+    // 1 disables pipelining. Anything above 1 turns on keep-alive
+    #[arg(short, long, default_value_t = 1)]
+    pipeline_depth: usize,
 }
 
 #[tokio::main]
 async fn main() -> tokio::io::Result<()> {
     simple_logger::init_with_level(Level::Info).unwrap();
 
-    let features = Args::parse();
+    let mut features = Args::parse();
+
+    if features.pipeline_depth > 1 && !features.use_keep_alive {
+        warn!("Pipelining needs keep-alive, enabling it");
+        features.use_keep_alive = true;
+    }
 
     println!("Using features: {:?}", features);
 
     let mut router = Router::new();
 
     let index_handler = async_handler!(|_req, res| {
-        let mut f = File::open("public/index.html").await.unwrap();
-        let mut buffer = String::new();
-
-        f.read_to_string(&mut buffer).await.unwrap();
-
-        res.add_header(HttpHeaderName::from("Content-Type"), "text/html");
-        res.body = buffer;
+        *res = HttpResponse::file("public/index.html").await;
     });
 
     router.add_route("/", index_handler);
@@ -55,15 +58,8 @@ async fn main() -> tokio::io::Result<()> {
     Ok(())
 }
 
-async fn kitty_handler(_req: &HttpRequest, res: &mut HttpResponse) {
-    let mut f = File::open("public/kitty.html").await.unwrap();
-    let mut buffer = String::new();
-
-    f.read_to_string(&mut buffer).await.unwrap();
-
-    res.add_header(HttpHeaderName::from("Content-Type"), "text/html");
-
-    res.body = buffer;
+async fn kitty_handler(_req: &mut HttpRequest, res: &mut HttpResponse) {
+    *res = HttpResponse::file("public/kitty.html").await;
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -71,15 +67,10 @@ struct Greeting {
     hello: String,
 }
 
-async fn json_handler(_req: &HttpRequest, res: &mut HttpResponse) {
+async fn json_handler(_req: &mut HttpRequest, res: &mut HttpResponse) {
     let greeting = Greeting {
         hello: "world".to_string(),
     };
 
-    let serialized = serde_json::to_string(&greeting).unwrap();
-
-    res.add_header(HttpHeaderName::from("Content-Type"), "application/json");
-
-    res.body = serialized;
-    res.status_code = 200;
+    res.set_json(&greeting);
 }
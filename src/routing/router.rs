@@ -1,21 +1,32 @@
 use log::info;
 use std::{collections::HashMap, net::SocketAddr, pin::Pin};
-use tokio::{
-    fs::File,
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::tcp::OwnedWriteHalf,
+use tokio::io::AsyncWrite;
+
+use crate::http::{
+    body::ResponseBody, headers::HttpHeaderName, request::HttpRequest, response::HttpResponse,
+    status::StatusCode, target::RequestTarget, version::HttpVersion,
 };
 
-use crate::http::{headers::HttpHeaderName, request::HttpRequest, response::HttpResponse};
+/// Methods any route accepts, advertised by `OPTIONS *`. CONNECT is left
+/// out since the server is not a tunnelling proxy.
+const ALLOWED_METHODS: &str = "GET, HEAD, POST, PUT, DELETE, OPTIONS, TRACE, PATCH";
 
 pub struct Router {
     routes: HashMap<String, HandlerFn>,
+    body_limits: HashMap<String, usize>,
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Router {
     pub fn new() -> Router {
         Router {
             routes: HashMap::new(),
+            body_limits: HashMap::new(),
         }
     }
 
@@ -23,76 +34,118 @@ impl Router {
         self.routes.insert(path.to_string(), handler);
     }
 
-    pub async fn match_route(
-        &self,
-        writer: &mut OwnedWriteHalf,
-        addr: SocketAddr,
-        request: &HttpRequest,
-        keep_alive: bool,
-    ) -> Result<(), std::io::Error> {
-        info!("Handling request to path: {}", request.path);
+    /// Overrides the server-wide `max_body_size` for one route. The request
+    /// line and headers are read before routing, so their limits cannot.
+    pub fn set_route_body_limit(&mut self, path: &str, max_body_size: usize) {
+        self.body_limits.insert(path.to_string(), max_body_size);
+    }
 
-        let route = self.routes.get(&request.path);
+    pub fn body_limit_for(&self, path: &str) -> Option<usize> {
+        self.body_limits.get(path).copied()
+    }
+
+    /// Runs the handler matching the request path, or the 404 page. Targets
+    /// without a path are answered here: `OPTIONS *` with the methods the
+    /// server supports and CONNECT with 405.
+    pub async fn handle(&self, request: &mut HttpRequest) -> HttpResponse {
+        info!("Handling request to target: {}", request.target);
 
         let mut res = HttpResponse::new();
 
+        match request.target {
+            RequestTarget::Asterisk => {
+                res.add_header(HttpHeaderName::Allow, ALLOWED_METHODS);
+                return res;
+            }
+            RequestTarget::Authority(_) => {
+                res.status_code = StatusCode::METHOD_NOT_ALLOWED;
+                res.add_header(HttpHeaderName::Allow, ALLOWED_METHODS);
+                return res;
+            }
+            RequestTarget::Origin(_) | RequestTarget::Absolute { .. } => {}
+        }
+
+        let route = self.routes.get(&request.path);
+
         if let Some(r) = route {
             r(request, &mut res).await;
         } else {
             not_found(&mut res).await;
         }
 
-        // TODO:
-        // Make sure all "needed" headers are included
-        res.add_header(
-            HttpHeaderName::ContentLength,
-            &res.content_length().to_string(),
-        );
+        res
+    }
+}
 
-        if keep_alive {
-            res.add_header(HttpHeaderName::Connection, "Keep-Alive");
-            res.add_header(HttpHeaderName::KeepAlive, "timeout=15, max=100");
-        } else {
-            res.add_header(HttpHeaderName::Connection, "Close");
+/// Frames and writes `res`, returning whether the connection can be reused.
+/// Streaming bodies are chunked on HTTP/1.1, while HTTP/1.0 clients can only
+/// learn where they end from the connection closing. `server` is the
+/// `Server` header to send, if any.
+pub async fn send_response<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    addr: SocketAddr,
+    res: &mut HttpResponse,
+    mut keep_alive: bool,
+    server: Option<&str>,
+) -> Result<bool, std::io::Error> {
+    res.add_default_headers(server);
+
+    match res.content_length() {
+        // These never carry a body, so there is nothing to frame.
+        _ if res.status_code.is_informational() || res.status_code == StatusCode::NO_CONTENT => {
+            res.remove_header(&HttpHeaderName::ContentLength);
+            res.remove_header(&HttpHeaderName::TransferEncoding);
+            res.body = ResponseBody::new();
         }
+        Some(len) => {
+            res.remove_header(&HttpHeaderName::TransferEncoding);
+            res.add_header(HttpHeaderName::ContentLength, &len.to_string());
+        }
+        None if res.version >= HttpVersion::Http11 => {
+            res.remove_header(&HttpHeaderName::ContentLength);
+            res.add_header(HttpHeaderName::TransferEncoding, "chunked");
+        }
+        None => {
+            res.remove_header(&HttpHeaderName::ContentLength);
+            res.remove_header(&HttpHeaderName::TransferEncoding);
+            keep_alive = false;
+        }
+    }
 
-        let response = format!(
-            "HTTP/1.1 {}\r\n{}\r\n\r\n{}",
-            res.status_code,
-            res.headers.as_str(),
-            res.body
-        );
+    if keep_alive {
+        res.add_header(HttpHeaderName::Connection, "Keep-Alive");
+        res.add_header(HttpHeaderName::KeepAlive, "timeout=15, max=100");
+    } else {
+        res.add_header(HttpHeaderName::Connection, "Close");
+        res.remove_header(&HttpHeaderName::KeepAlive);
+    }
 
-        info!(
-            "Sending response to peer: {} with status: {}, Content-Length: {}, Content-Type: {}",
-            addr,
-            res.status_code,
-            res.content_length(),
-            res.headers.content_type().unwrap_or("text/html")
-        );
+    info!(
+        "Sending response to peer: {} with status: {}, Content-Length: {}, Content-Type: {}",
+        addr,
+        res.status_code,
+        res.content_length()
+            .map_or_else(|| "streamed".to_string(), |len| len.to_string()),
+        res.headers.content_type().unwrap_or("text/html")
+    );
 
-        writer.write_all(response.as_bytes()).await?;
-        // writer.flush().await?;
+    res.write_to(writer).await?;
+    // writer.flush().await?;
 
-        info!("Response sent to peer: {}", addr);
+    info!("Response sent to peer: {}", addr);
 
-        Ok(())
-    }
+    Ok(keep_alive)
 }
 
 async fn not_found(res: &mut HttpResponse) {
-    let mut f = File::open("public/404.html").await.unwrap();
-    let mut buffer = String::new();
-
-    f.read_to_string(&mut buffer).await.unwrap();
-
-    res.body = buffer;
-    res.status_code = 404;
+    *res = HttpResponse::file("public/404.html")
+        .await
+        .with_status(StatusCode::NOT_FOUND);
 }
 
 pub type HandlerFn = Box<
     dyn for<'a> Fn(
-            &'a HttpRequest,
+            &'a mut HttpRequest,
             &'a mut HttpResponse,
         ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>>
         + Send
@@ -105,7 +158,7 @@ macro_rules! async_handler {
         use std::{future::Future, pin::Pin};
 
         fn handler<'a>(
-            $req: &'a $crate::http::request::HttpRequest,
+            $req: &'a mut $crate::http::request::HttpRequest,
             $res: &'a mut $crate::http::response::HttpResponse,
         ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
             Box::pin(async move $body)
@@ -128,9 +181,9 @@ mod tests {
     fn function_works() {
         let mut router = Router::new();
 
-        async fn _handler(_req: &HttpRequest, res: &mut HttpResponse) {
-            res.status_code = 200;
-            res.body = String::from("hello");
+        async fn _handler(_req: &mut HttpRequest, res: &mut HttpResponse) {
+            res.status_code = StatusCode::OK;
+            res.body = "hello".into();
         }
 
         router.add_route("/", async_fn_handler!(_handler));
@@ -141,10 +194,20 @@ mod tests {
         let mut router = Router::new();
 
         let handler: HandlerFn = async_handler!(|_req, res| {
-            res.status_code = 200;
-            res.body = String::from("hello");
+            res.status_code = StatusCode::OK;
+            res.body = "hello".into();
         });
 
         router.add_route("/", handler);
     }
+
+    #[test]
+    fn route_body_limits_override_defaults() {
+        let mut router = Router::new();
+
+        router.set_route_body_limit("/upload", 1024);
+
+        assert_eq!(router.body_limit_for("/upload"), Some(1024));
+        assert_eq!(router.body_limit_for("/"), None);
+    }
 }
@@ -3,21 +3,40 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 
+use futures_util::StreamExt;
+use futures_util::stream::FuturesOrdered;
 use log::{error, info};
-use tokio::net::TcpListener;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
 use tokio::time::timeout;
-use tokio::{io::BufReader, net::TcpStream};
 
 use crate::Args;
-use crate::http::headers::{ConnectionHeaderValue, HttpHeaderName, HttpHeaderValue, HttpHeaders};
+use crate::http::body::{BodyFraming, BoxedReader};
+use crate::http::compression::Compression;
+use crate::http::error::HttpParseError;
+use crate::http::headers::{HttpHeaderName, HttpHeaders};
+use crate::http::limits::Limits;
 use crate::http::request::HttpRequest;
-use crate::routing::router;
+use crate::http::response::HttpResponse;
+use crate::http::status::StatusCode;
+use crate::http::version::HttpVersion;
+use crate::routing::router::{self, send_response};
+
+const DEFAULT_SERVER_HEADER: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// Largest body a pipelined request may have to be read ahead. Bigger and
+/// chunked bodies would otherwise sit in memory for every queued request.
+const READ_AHEAD_BODY_LIMIT: usize = 16 * 1024;
 
 pub struct Server {
     host: String,
     port: u16,
     router: router::Router,
     features: Args,
+    limits: Limits,
+    server_header: Option<String>,
+    compression: Option<Compression>,
 }
 
 impl Server {
@@ -27,9 +46,31 @@ impl Server {
             router,
             port,
             features,
+            limits: Limits::default(),
+            server_header: Some(DEFAULT_SERVER_HEADER.to_string()),
+            compression: Some(Compression::default()),
         }
     }
 
+    pub fn with_limits(mut self, limits: Limits) -> Server {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets how responses are compressed for clients that send
+    /// `Accept-Encoding`, or turns compression off with `None`.
+    pub fn with_compression(mut self, compression: Option<Compression>) -> Server {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the `Server` header sent with every response, or leaves it out
+    /// with `None`.
+    pub fn with_server_header(mut self, server_header: Option<&str>) -> Server {
+        self.server_header = server_header.map(str::to_string);
+        self
+    }
+
     pub async fn start(self) -> std::io::Result<()> {
         let srv_addr = format!("{}:{}", self.host, self.port);
         let listener = TcpListener::bind(&srv_addr).await?;
@@ -58,83 +99,870 @@ impl Server {
         socket: TcpStream,
         addr: SocketAddr,
     ) -> tokio::io::Result<()> {
-        let (reader, mut writer) = socket.into_split();
-        let mut buffered_reader = BufReader::new(reader);
+        let (reader, writer) = socket.into_split();
+
+        self.serve(reader, writer, addr).await
+    }
+
+    /// Runs the request/response loop over any pair of byte streams, e.g. the
+    /// halves of a TLS stream, a Unix socket or an in-memory duplex.
+    pub async fn serve<R, W>(
+        &self,
+        reader: R,
+        mut writer: W,
+        addr: SocketAddr,
+    ) -> tokio::io::Result<()>
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+        W: AsyncWrite + Unpin,
+    {
+        let mut reader: BoxedReader = Box::new(BufReader::new(reader));
+
+        if self.features.use_keep_alive && self.features.pipeline_depth > 1 {
+            return self.serve_pipelined(reader, &mut writer, addr).await;
+        }
 
         loop {
-            let result = timeout(
-                Duration::from_secs(30),
-                HttpRequest::parse(&mut buffered_reader),
-            )
-            .await?;
+            let result = timeout(Duration::from_secs(30), self.read_request(reader)).await?;
 
-            let req = match result {
+            let (req, continue_rx) = match result {
                 Ok(r) => r,
-                Err(e) => return Err(e),
+                Err(HttpParseError::Io(e)) => return Err(e),
+                Err(HttpParseError::ConnectionClosed) => {
+                    info!("Connection closed by {}", addr);
+                    break;
+                }
+                Err(e) => {
+                    error!("Rejecting request from {}: {}", addr, e);
+                    reject_request(&mut writer, &e, self.server_header()).await?;
+                    break;
+                }
             };
 
-            let body = match &req.body.as_ref() {
-                Some(b) => String::from_utf8_lossy(b).to_string(),
-                None => "no body".to_string(),
+            reader = match self
+                .serve_streaming(&mut writer, addr, req, continue_rx)
+                .await?
+            {
+                Some(r) => r,
+                None => break,
             };
 
-            let keep_alive = if self.features.use_keep_alive {
-                should_use_keep_alive(&req.headers)
-            } else {
-                info!("No keep-alive feature used, skpping");
-                false
-            };
+            info!("Keep-alive configured, continuing with connection");
+        }
+
+        Ok(())
+    }
+
+    /// Pipelined variant of the connection loop. Requests are read ahead with
+    /// their bodies buffered and up to `pipeline_depth` handlers run
+    /// concurrently, while responses are still written in request order.
+    /// Requests that wait for `100 Continue` or carry a large or chunked body
+    /// are not read ahead, so they are served on their own once everything
+    /// before them has been answered.
+    async fn serve_pipelined<W: AsyncWrite + Unpin>(
+        &self,
+        reader: BoxedReader,
+        writer: &mut W,
+        addr: SocketAddr,
+    ) -> tokio::io::Result<()> {
+        let depth = self.features.pipeline_depth;
+
+        let mut in_flight = FuturesOrdered::new();
+        let mut reading = Some(Box::pin(self.read_buffered(reader)));
+        let mut deferred: Option<(BoxedReader, HttpRequest)> = None;
+        let mut failure: Option<HttpParseError> = None;
+
+        loop {
+            if in_flight.is_empty() {
+                match failure.take() {
+                    Some(HttpParseError::Io(e)) => return Err(e),
+                    Some(HttpParseError::ConnectionClosed) => {
+                        info!("Connection closed by {}", addr);
+                        return Ok(());
+                    }
+                    Some(e) => {
+                        error!("Rejecting request from {}: {}", addr, e);
+                        return reject_request(writer, &e, self.server_header()).await;
+                    }
+                    None => {}
+                }
 
-            info!(
-                "Handling request from {}, NumHeaders: {}, LenBody: {}",
-                addr,
-                req.headers.values.len(),
-                body.len(),
-            );
+                if let Some((reader, mut req)) = deferred.take() {
+                    let continue_rx = match self.attach_body(&mut req, reader) {
+                        Ok(rx) => rx,
+                        Err(e) => {
+                            error!("Rejecting request from {}: {}", addr, e);
+                            return reject_request(writer, &e, self.server_header()).await;
+                        }
+                    };
 
-            self.router
-                .match_route(&mut writer, addr, &req, keep_alive)
-                .await?;
+                    match self.serve_streaming(writer, addr, req, continue_rx).await? {
+                        Some(reader) => reading = Some(Box::pin(self.read_buffered(reader))),
+                        None => return Ok(()),
+                    }
+                }
 
-            if !keep_alive {
-                info!("No keep-alive configured, exiting");
-                break;
+                if reading.is_none() {
+                    return Ok(());
+                }
             }
 
-            info!("Keep-alive configured, continuing with connection");
-        }
+            let can_read = reading.is_some() && in_flight.len() < depth;
 
-        Ok(())
+            tokio::select! {
+                Some((version, res, keep_alive)) = in_flight.next() => {
+                    let mut res: HttpResponse = res;
+                    res.version = version;
+                    if !send_response(writer, addr, &mut res, keep_alive, self.server_header()).await? {
+                        return Ok(());
+                    }
+                }
+                (reader, result) = async { reading.as_mut().unwrap().await }, if can_read => {
+                    reading = None;
+
+                    match result {
+                        Ok(NextRequest::Buffered(req)) => {
+                            let keep_alive = self.keep_alive_for(&req);
+                            in_flight.push_back(self.run_buffered(req, keep_alive));
+
+                            if keep_alive {
+                                reading = Some(Box::pin(self.read_buffered(reader)));
+                            }
+                        }
+                        Ok(NextRequest::Streaming(req)) => deferred = Some((reader, req)),
+                        Err(e) => failure = Some(e),
+                    }
+                }
+                else => return Ok(()),
+            }
+        }
     }
-}
 
-fn should_use_keep_alive(headers: &HttpHeaders) -> bool {
-    let connection_header = headers.get(&HttpHeaderName::Connection);
-    if let Some(ch) = connection_header {
-        let res = ch.get(0);
-        let header_value = match res {
-            None => {
-                error!("Found connection header but now value. Don't use keep-alive");
-                return false;
+    /// Handles a request whose body streams from the connection, returning
+    /// the reader if the connection can be reused.
+    async fn serve_streaming<W: AsyncWrite + Unpin>(
+        &self,
+        writer: &mut W,
+        addr: SocketAddr,
+        mut req: HttpRequest,
+        continue_rx: Option<oneshot::Receiver<()>>,
+    ) -> tokio::io::Result<Option<BoxedReader>> {
+        let mut keep_alive = self.keep_alive_for(&req);
+
+        info!(
+            "Handling request from {}, NumHeaders: {}, LenBody: {}",
+            addr,
+            req.headers.len(),
+            req.headers.content_length().unwrap_or(0),
+        );
+
+        let mut res = self.dispatch(writer, &mut req, continue_rx).await?;
+        self.compress(&req, &mut res);
+
+        if req.body.is_awaiting_continue() {
+            info!("Body from {} was never requested, closing connection", addr);
+            keep_alive = false;
+        }
+
+        res.version = req.http_version;
+        keep_alive =
+            send_response(writer, addr, &mut res, keep_alive, self.server_header()).await?;
+
+        if !keep_alive {
+            info!("No keep-alive configured, exiting");
+            return Ok(None);
+        }
+
+        match req.body.finish().await {
+            Ok(reader) => Ok(reader),
+            Err(e) => {
+                error!("Failed to drain request body from {}: {}", addr, e);
+                Ok(None)
             }
-            Some(hv) => hv,
+        }
+    }
+
+    fn compress(&self, req: &HttpRequest, res: &mut HttpResponse) {
+        if let Some(compression) = &self.compression {
+            compression.apply(&req.headers, res);
+        }
+    }
+
+    fn server_header(&self) -> Option<&str> {
+        self.server_header.as_deref()
+    }
+
+    fn keep_alive_for(&self, req: &HttpRequest) -> bool {
+        if self.features.use_keep_alive {
+            should_use_keep_alive(req.http_version, &req.headers)
+        } else {
+            info!("No keep-alive feature used, skpping");
+            false
+        }
+    }
+
+    /// Server-wide limits with the route's body limit, if it set one.
+    fn limits_for(&self, req: &HttpRequest) -> Limits {
+        match self.router.body_limit_for(&req.path) {
+            Some(max_body_size) => Limits {
+                max_body_size,
+                ..self.limits
+            },
+            None => self.limits,
+        }
+    }
+
+    async fn read_request(
+        &self,
+        mut reader: BoxedReader,
+    ) -> Result<(HttpRequest, Option<oneshot::Receiver<()>>), HttpParseError> {
+        let mut req = HttpRequest::parse_head(&mut reader, &self.limits).await?;
+        let continue_rx = self.attach_body(&mut req, reader)?;
+
+        Ok((req, continue_rx))
+    }
+
+    /// Lends the reader to the request body, deferring `100 Continue` until
+    /// the handler reads it.
+    fn attach_body(
+        &self,
+        req: &mut HttpRequest,
+        reader: BoxedReader,
+    ) -> Result<Option<oneshot::Receiver<()>>, HttpParseError> {
+        let expects_continue = req.expects_continue()?;
+
+        let limits = self.limits_for(req);
+        req.attach_body(reader, &limits)?;
+
+        let continue_rx = if expects_continue && req.body_framing(&limits)? != BodyFraming::Empty {
+            Some(req.body.expect_continue())
+        } else {
+            None
         };
 
-        if let HttpHeaderValue::Connection(cv) = header_value {
-            if let ConnectionHeaderValue::Close = cv {
-                info!("Client requested connection close");
-                return false;
-            }
-            if let ConnectionHeaderValue::KeepAlive = cv {
-                info!("Client requested keep alive");
-                return true;
+        Ok(continue_rx)
+    }
+
+    /// Reads the next request with its body buffered, so the one after it
+    /// can be read while this one is handled.
+    async fn read_buffered(
+        &self,
+        mut reader: BoxedReader,
+    ) -> (BoxedReader, Result<NextRequest, HttpParseError>) {
+        let result = match timeout(
+            Duration::from_secs(30),
+            self.read_buffered_request(&mut reader),
+        )
+        .await
+        {
+            Ok(result) => result,
+            Err(elapsed) => Err(HttpParseError::Io(elapsed.into())),
+        };
+
+        (reader, result)
+    }
+
+    async fn read_buffered_request(
+        &self,
+        reader: &mut BoxedReader,
+    ) -> Result<NextRequest, HttpParseError> {
+        let mut req = HttpRequest::parse_head(reader, &self.limits).await?;
+
+        let expects_continue = req.expects_continue()?;
+        let limits = self.limits_for(&req);
+        let read_ahead = match req.body_framing(&limits)? {
+            BodyFraming::Empty => true,
+            BodyFraming::Length(len) => !expects_continue && len <= READ_AHEAD_BODY_LIMIT,
+            BodyFraming::Chunked => false,
+        };
+
+        if !read_ahead {
+            return Ok(NextRequest::Streaming(req));
+        }
+
+        req.read_body(reader, &limits).await?;
+
+        Ok(NextRequest::Buffered(req))
+    }
+
+    async fn run_buffered(
+        &self,
+        mut req: HttpRequest,
+        keep_alive: bool,
+    ) -> (HttpVersion, HttpResponse, bool) {
+        info!(
+            "Handling pipelined request, NumHeaders: {}, LenBody: {}",
+            req.headers.len(),
+            req.headers.content_length().unwrap_or(0),
+        );
+
+        let mut res = self.router.handle(&mut req).await;
+        self.compress(&req, &mut res);
+
+        (req.http_version, res, keep_alive)
+    }
+
+    /// Runs the handler. If the client sent `Expect: 100-continue`, the
+    /// interim response goes out as soon as the handler starts reading the
+    /// body, so handlers that reject the request never receive the upload.
+    async fn dispatch<W: AsyncWrite + Unpin>(
+        &self,
+        writer: &mut W,
+        req: &mut HttpRequest,
+        continue_rx: Option<oneshot::Receiver<()>>,
+    ) -> tokio::io::Result<HttpResponse> {
+        let handler = self.router.handle(req);
+
+        let Some(mut continue_rx) = continue_rx else {
+            return Ok(handler.await);
+        };
+
+        tokio::pin!(handler);
+
+        tokio::select! {
+            res = &mut handler => Ok(res),
+            Ok(()) = &mut continue_rx => {
+                writer.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").await?;
+                Ok(handler.await)
             }
         }
     }
-    info!("No connection header, defaulting to keep-alive (HTTP/1.1)");
-    return true;
+}
+
+enum NextRequest {
+    Buffered(HttpRequest),
+    /// Its body is still on the connection, either because it waits for
+    /// `100 Continue` or because it is too large to buffer ahead.
+    Streaming(HttpRequest),
+}
+
+fn should_use_keep_alive(version: HttpVersion, headers: &HttpHeaders) -> bool {
+    if headers.connection_has("close") {
+        info!("Client requested connection close");
+        return false;
+    }
+
+    if version.is_persistent_by_default() {
+        info!(
+            "No connection close requested, defaulting to keep-alive ({})",
+            version
+        );
+        return true;
+    }
+
+    if headers.connection_has("keep-alive") {
+        info!("Client requested keep alive ({})", version);
+        return true;
+    }
+
+    info!(
+        "No keep-alive requested, closing after response ({})",
+        version
+    );
+    false
+}
+
+async fn reject_request<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    err: &HttpParseError,
+    server: Option<&str>,
+) -> tokio::io::Result<()> {
+    let mut res = HttpResponse::new();
+    res.add_default_headers(server);
+
+    res.status_code = err.status_code().unwrap_or(StatusCode::BAD_REQUEST);
+    let body = err.to_string();
+
+    res.add_header(HttpHeaderName::ContentType, "text/plain");
+    res.add_header(HttpHeaderName::ContentLength, &body.len().to_string());
+    res.body = body.into();
+    res.add_header(HttpHeaderName::Connection, "Close");
+
+    res.write_to(writer).await
 }
 
 fn handle_socket_error<T: Error>(e: T, addr: SocketAddr) {
     error!("Error handling {}: {}", addr, e);
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::LazyLock;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, duplex, split};
+    use tokio::sync::Barrier;
+
+    use super::*;
+    use crate::http::date::HttpDate;
+
+    /// Only lets `/rendezvous` handlers through in pairs, so they finish only
+    /// if they run at the same time.
+    static RENDEZVOUS: LazyLock<Barrier> = LazyLock::new(|| Barrier::new(2));
+
+    fn test_server() -> Server {
+        pipelined_server(1)
+    }
+
+    fn pipelined_server(pipeline_depth: usize) -> Server {
+        let mut router = router::Router::new();
+
+        router.add_route(
+            "/slow",
+            crate::async_handler!(|_req, res| {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                res.body = "slow".into();
+            }),
+        );
+
+        router.add_route(
+            "/rendezvous",
+            crate::async_handler!(|_req, res| {
+                let met = timeout(Duration::from_secs(5), RENDEZVOUS.wait()).await;
+                res.body = if met.is_ok() { "together" } else { "alone" }.into();
+            }),
+        );
+
+        router.add_route(
+            "/hello",
+            crate::async_handler!(|_req, res| {
+                res.body = "hello".into();
+            }),
+        );
+
+        router.add_route(
+            "/empty",
+            crate::async_handler!(|_req, res| {
+                *res = HttpResponse::no_content();
+            }),
+        );
+
+        router.add_route(
+            "/stream",
+            crate::async_handler!(|_req, res| {
+                let (tx, body) = crate::http::body::ResponseBody::channel(4);
+                tokio::spawn(async move {
+                    tx.send("hel").await.unwrap();
+                    tx.send("lo").await.unwrap();
+
+                    let mut trailers = HttpHeaders::new();
+                    trailers.append(HttpHeaderName::from("X-Sum"), "5");
+                    tx.finish(trailers).await.unwrap();
+                });
+
+                res.body = body;
+            }),
+        );
+
+        router.add_route(
+            "/echo",
+            crate::async_handler!(|req, res| {
+                while let Some(chunk) = req.body.chunk().await.unwrap() {
+                    res.body.extend_from_slice(&chunk);
+                }
+            }),
+        );
+
+        let features = Args {
+            use_keep_alive: true,
+            pipeline_depth,
+        };
+
+        Server::new(router, "127.0.0.1", 0, features)
+    }
+
+    async fn roundtrip(server: &Server, request: &[u8]) -> String {
+        let (client, conn) = duplex(64 * 1024);
+        let (reader, writer) = split(conn);
+        let (mut client_reader, mut client_writer) = split(client);
+
+        client_writer.write_all(request).await.unwrap();
+        client_writer.shutdown().await.unwrap();
+
+        server
+            .serve(reader, writer, "127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+
+        let mut response = String::new();
+        client_reader.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn serves_over_in_memory_stream() {
+        let server = test_server();
+
+        let response = roundtrip(&server, b"GET /hello HTTP/1.1\r\nHost: test\r\n\r\n").await;
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nhello"));
+    }
+
+    #[tokio::test]
+    async fn routes_on_path_of_every_target_form() {
+        let server = test_server();
+
+        let response = roundtrip(
+            &server,
+            b"GET http://proxy.test/hello HTTP/1.1\r\nHost: test\r\n\r\n\
+              OPTIONS * HTTP/1.1\r\nHost: test\r\n\r\n\
+              CONNECT test:443 HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+
+        let responses: Vec<&str> = response.split("HTTP/1.1 ").skip(1).collect();
+        assert_eq!(responses.len(), 3);
+        assert!(responses[0].starts_with("200") && responses[0].ends_with("hello"));
+        assert!(responses[1].starts_with("200") && responses[1].contains("Allow: GET"));
+        assert!(responses[2].starts_with("405"));
+    }
+
+    #[tokio::test]
+    async fn serves_keep_alive_requests_in_order() {
+        let server = test_server();
+
+        let response = roundtrip(
+            &server,
+            b"GET /hello HTTP/1.1\r\nHost: test\r\n\r\nGET /missing HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+
+        let first = response.find("HTTP/1.1 200").unwrap();
+        let second = response.find("HTTP/1.1 404").unwrap();
+        assert!(first < second);
+    }
+
+    #[tokio::test]
+    async fn streams_request_body_to_handler() {
+        let server = test_server();
+
+        let response = roundtrip(
+            &server,
+            b"POST /echo HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n3\r\nabc\r\n3\r\ndef\r\n0\r\n\r\n",
+        )
+        .await;
+
+        assert!(response.ends_with("\r\n\r\nabcdef"));
+    }
+
+    #[tokio::test]
+    async fn drains_unread_body_before_next_request() {
+        let server = test_server();
+
+        let response = roundtrip(
+            &server,
+            b"POST /hello HTTP/1.1\r\nHost: test\r\nContent-Length: 37\r\n\r\nGET /missing HTTP/1.1\r\nHost: test\r\n\r\nGET /echo HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+
+        assert_eq!(response.matches("HTTP/1.1 200").count(), 2);
+        assert!(!response.contains("HTTP/1.1 404"));
+    }
+
+    #[tokio::test]
+    async fn sends_continue_when_handler_reads_body() {
+        let server = Arc::new(test_server());
+        let (client, conn) = duplex(64 * 1024);
+        let (reader, writer) = split(conn);
+        let (mut client_reader, mut client_writer) = split(client);
+
+        let task = tokio::spawn({
+            let server = Arc::clone(&server);
+            async move {
+                server
+                    .serve(reader, writer, "127.0.0.1:0".parse().unwrap())
+                    .await
+            }
+        });
+
+        client_writer
+            .write_all(b"POST /echo HTTP/1.1\r\nHost: test\r\nExpect: 100-continue\r\nContent-Length: 5\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut interim = [0u8; 25];
+        client_reader.read_exact(&mut interim).await.unwrap();
+        assert_eq!(&interim, b"HTTP/1.1 100 Continue\r\n\r\n");
+
+        client_writer.write_all(b"hello").await.unwrap();
+        task.await.unwrap().unwrap();
+
+        let mut response = String::new();
+        client_reader.read_to_string(&mut response).await.unwrap();
+        assert!(response.ends_with("\r\n\r\nhello"));
+    }
+
+    #[tokio::test]
+    async fn skips_continue_when_handler_ignores_body() {
+        let server = test_server();
+
+        let response = roundtrip(
+            &server,
+            b"POST /hello HTTP/1.1\r\nHost: test\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n",
+        )
+        .await;
+
+        assert!(!response.contains("100 Continue"));
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Connection: close"));
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_upload_before_continue() {
+        let mut server = test_server();
+        server.router.set_route_body_limit("/echo", 4);
+
+        let response = roundtrip(
+            &server,
+            b"POST /echo HTTP/1.1\r\nHost: test\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n",
+        )
+        .await;
+
+        assert!(!response.contains("100 Continue"));
+        assert!(response.starts_with("HTTP/1.1 413 Content Too Large\r\n"));
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_expectation() {
+        let server = test_server();
+
+        let response = roundtrip(
+            &server,
+            b"POST /echo HTTP/1.1\r\nHost: test\r\nExpect: something-else\r\nContent-Length: 5\r\n\r\nhello",
+        )
+        .await;
+
+        assert!(response.starts_with("HTTP/1.1 417 Expectation Failed\r\n"));
+    }
+
+    #[test]
+    fn applies_version_specific_persistence() {
+        let mut headers = HttpHeaders::new();
+        assert!(should_use_keep_alive(HttpVersion::Http11, &headers));
+        assert!(!should_use_keep_alive(HttpVersion::Http10, &headers));
+
+        headers.append(HttpHeaderName::Connection, "Keep-Alive");
+        assert!(should_use_keep_alive(HttpVersion::Http10, &headers));
+
+        let mut headers = HttpHeaders::new();
+        headers.append(HttpHeaderName::Connection, "Upgrade, close");
+        assert!(!should_use_keep_alive(HttpVersion::Http11, &headers));
+    }
+
+    #[tokio::test]
+    async fn closes_http10_connections_by_default() {
+        let server = test_server();
+
+        let response = roundtrip(
+            &server,
+            b"GET /hello HTTP/1.0\r\n\r\nGET /hello HTTP/1.0\r\n\r\n",
+        )
+        .await;
+
+        assert!(response.starts_with("HTTP/1.0 200 OK\r\n"));
+        assert!(response.contains("Connection: close"));
+        assert_eq!(response.matches("HTTP/1.0 200").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn adds_date_and_server_headers() {
+        let response = roundtrip(
+            &test_server(),
+            b"GET /hello HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+
+        let date = response
+            .lines()
+            .find_map(|line| line.strip_prefix("Date: "))
+            .unwrap();
+        assert!(date.parse::<HttpDate>().is_ok());
+        assert!(response.contains("\r\nServer: rust-async-http/"));
+
+        let server = test_server().with_server_header(None);
+        let response = roundtrip(&server, b"GET /hello HTTP/1.1\r\n\r\n").await;
+
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(response.contains("\r\nDate: "));
+        assert!(!response.contains("Server:"));
+    }
+
+    #[tokio::test]
+    async fn sends_no_framing_headers_with_204() {
+        let server = test_server();
+
+        let response = roundtrip(
+            &server,
+            b"GET /empty HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+
+        assert!(response.starts_with("HTTP/1.1 204 No Content\r\n"));
+        assert!(!response.contains("Content-Length"));
+        assert!(!response.contains("Transfer-Encoding"));
+    }
+
+    #[tokio::test]
+    async fn streams_chunked_body_with_trailers() {
+        let server = test_server();
+
+        let response = roundtrip(
+            &server,
+            b"GET /stream HTTP/1.1\r\nHost: test\r\n\r\nGET /hello HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+
+        let (first, second) = response.split_at(response.rfind("HTTP/1.1 200").unwrap());
+        assert!(first.contains("\r\nTransfer-Encoding: chunked\r\n"));
+        assert!(!first.contains("Content-Length"));
+        assert!(first.ends_with("\r\n\r\n3\r\nhel\r\n2\r\nlo\r\n0\r\nX-Sum: 5\r\n\r\n"));
+        assert!(second.ends_with("hello"));
+    }
+
+    #[tokio::test]
+    async fn closes_after_streamed_http10_body() {
+        let server = test_server();
+
+        let response = roundtrip(
+            &server,
+            b"GET /stream HTTP/1.0\r\nConnection: keep-alive\r\n\r\nGET /hello HTTP/1.0\r\n\r\n",
+        )
+        .await;
+
+        assert!(response.contains("\r\nConnection: close\r\n"));
+        assert!(!response.contains("Transfer-Encoding"));
+        assert!(response.ends_with("\r\n\r\nhello"));
+        assert_eq!(response.matches("HTTP/1.0 200").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn pipelined_responses_keep_request_order() {
+        let server = pipelined_server(4);
+
+        let response = roundtrip(
+            &server,
+            b"GET /slow HTTP/1.1\r\nHost: test\r\n\r\nGET /rendezvous HTTP/1.1\r\nHost: test\r\n\r\nGET /rendezvous HTTP/1.1\r\nHost: test\r\n\r\nPOST /echo HTTP/1.1\r\nHost: test\r\nContent-Length: 4\r\n\r\nfastGET /hello HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+
+        let slow = response.find("slow").unwrap();
+        let fast = response.find("fast").unwrap();
+        let hello = response.find("hello").unwrap();
+        assert!(slow < fast && fast < hello);
+        assert_eq!(response.matches("together").count(), 2);
+        assert_eq!(response.matches("HTTP/1.1 200").count(), 5);
+    }
+
+    #[tokio::test]
+    async fn pipelining_answers_parse_error_after_earlier_responses() {
+        let server = pipelined_server(4);
+
+        let response = roundtrip(
+            &server,
+            b"GET /slow HTTP/1.1\r\nHost: test\r\n\r\nGET /hello HTTP/1.1\r\nHost: test\r\nNoColon\r\n\r\n",
+        )
+        .await;
+
+        let ok = response.find("HTTP/1.1 200").unwrap();
+        let bad = response.find("HTTP/1.1 400").unwrap();
+        assert!(ok < bad);
+    }
+
+    #[tokio::test]
+    async fn pipelining_serves_expect_continue_on_its_own() {
+        let server = pipelined_server(4);
+
+        let response = roundtrip(
+            &server,
+            b"GET /slow HTTP/1.1\r\nHost: test\r\n\r\nPOST /echo HTTP/1.1\r\nHost: test\r\nExpect: 100-continue\r\nContent-Length: 4\r\n\r\nbodyGET /hello HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+
+        let slow = response.find("slow").unwrap();
+        let body = response.find("body").unwrap();
+        let hello = response.find("hello").unwrap();
+        assert!(slow < body && body < hello);
+    }
+
+    #[tokio::test]
+    async fn pipelining_reads_ahead_small_bodies_only() {
+        let server = pipelined_server(4);
+
+        let small = b"POST /echo HTTP/1.1\r\nHost: test\r\nContent-Length: 4\r\n\r\nbody";
+        let mut reader: BoxedReader = Box::new(&small[..]);
+        let next = server.read_buffered_request(&mut reader).await.unwrap();
+        assert!(matches!(next, NextRequest::Buffered(_)));
+
+        let large = format!(
+            "POST /echo HTTP/1.1\r\nHost: test\r\nContent-Length: {}\r\n\r\n",
+            READ_AHEAD_BODY_LIMIT + 1
+        );
+        let mut reader: BoxedReader = Box::new(std::io::Cursor::new(large));
+        let next = server.read_buffered_request(&mut reader).await.unwrap();
+        assert!(matches!(next, NextRequest::Streaming(_)));
+
+        let chunked = b"POST /echo HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: chunked\r\n\r\n";
+        let mut reader: BoxedReader = Box::new(&chunked[..]);
+        let next = server.read_buffered_request(&mut reader).await.unwrap();
+        assert!(matches!(next, NextRequest::Streaming(_)));
+    }
+
+    #[tokio::test]
+    async fn pipelining_serves_large_bodies_in_sequence() {
+        let server = pipelined_server(4);
+
+        let body = "x".repeat(READ_AHEAD_BODY_LIMIT + 1);
+        let request = format!(
+            "GET /slow HTTP/1.1\r\nHost: test\r\n\r\nPOST /echo HTTP/1.1\r\nHost: test\r\nContent-Length: {}\r\n\r\n{}POST /echo HTTP/1.1\r\nHost: test\r\nTransfer-Encoding: chunked\r\n\r\n7\r\nchunked\r\n0\r\n\r\nGET /hello HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n",
+            body.len(),
+            body
+        );
+
+        let response = roundtrip(&server, request.as_bytes()).await;
+
+        let slow = response.find("slow").unwrap();
+        let large = response.find(&body).unwrap();
+        let chunked = response.find("\r\n\r\nchunked").unwrap();
+        let hello = response.find("hello").unwrap();
+        assert!(slow < large && large < chunked && chunked < hello);
+        assert_eq!(response.matches("HTTP/1.1 200").count(), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_on_a_body_that_never_arrives() {
+        let server = test_server();
+
+        let (client, conn) = duplex(64 * 1024);
+        let (reader, writer) = split(conn);
+        let (mut client_reader, mut client_writer) = split(client);
+
+        // The body is announced but never sent, and the connection stays open.
+        client_writer
+            .write_all(b"POST /hello HTTP/1.1\r\nHost: test\r\nContent-Length: 10485760\r\n\r\n")
+            .await
+            .unwrap();
+
+        server
+            .serve(reader, writer, "127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+
+        let mut response = String::new();
+        client_reader.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+
+    #[tokio::test]
+    async fn answers_parse_errors_with_status() {
+        let server = test_server();
+
+        let response = roundtrip(
+            &server,
+            b"GET /hello HTTP/1.1\r\nHost: test\r\nNoColon\r\n\r\n",
+        )
+        .await;
+
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(response.contains("Connection: close"));
+    }
+}